@@ -1,18 +1,59 @@
+mod routes;
+mod server;
 #[cfg(test)]
 mod tests;
 mod utils;
 
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::http::header;
-use actix_web::middleware::{DefaultHeaders, Logger};
-use actix_web::{get, http, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use clap::{crate_version, Parser};
+use actix_web::middleware::{from_fn, Compress, Condition, DefaultHeaders, Logger};
+use actix_web::{
+    get, post, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use clap::{crate_version, Parser, Subcommand};
+use futures_util::future::{try_join_all, BoxFuture};
+use futures_util::FutureExt;
 use gethostname::gethostname;
-use log::{debug, info, LevelFilter};
+use log::{debug, error, info, warn, LevelFilter};
 use maud::{html, Markup, PreEscaped, DOCTYPE};
+use routes::admin::AdminState;
+use routes::cache::{cache, cache_for};
+use routes::client_ip::{access_logger, client_ip, ip, parse_trusted_proxy, TrustedProxies};
+use routes::compression::{
+    brotli_body, deflate_body, filter_accept_encoding, gzip_body, weaken_encoded_etag, zstd_body,
+    CompressionConfig, Encoding,
+};
+use routes::config::EffectiveConfig;
+use routes::connection::connection;
+use routes::cookies::{delete_cookies, get_cookies, set_cookies, sticky_session, StickyCookie};
+use routes::cors::{cors, CorsConfig};
+use routes::download::{download, limit_bandwidth, parse_rate, parse_size, MaxBandwidth};
+use routes::files::ServeDir;
+use routes::jwt::{jwt_inspect, JwtConfig};
+use routes::lifecycle::{track_in_flight, Lifecycle};
+use routes::limits::{limit_payload, BodyLimits};
+use routes::oidc::{OidcConfig, OidcProvider, SigningAlgorithm};
+use routes::proxy_protocol::{proxy_protocol, ProxyProtocolMode};
+use routes::range::range;
+use routes::reload::{apply_settings, Reloadable, Settings};
+use routes::upload::upload;
+use sd_notify::NotifyState;
 use serde::Deserialize;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
+use server::h3::{alt_svc, AltSvc};
+use server::listener::{parse_listen, ListenAddress, ListenSpec};
+use server::{parse_duration, parse_whole_seconds, ListenerOptions, ServerOptions};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::sync::OnceCell;
+use tonic_health::server::HealthReporter;
+use utils::config::{ConfigFormat, EffectiveOptions};
 use utils::logging::log_init;
 use utils::structs::{Result, WesterError};
 
@@ -27,6 +68,13 @@ struct Cli {
     #[arg(short, long, env, global = true, default_value = "9999")]
     port: u16,
 
+    /// Addresses to serve on instead of --bind and --port, as `HOST:PORT`,
+    /// `unix:PATH` or `fd:NAME` for sockets passed by systemd, each followed by
+    /// options overriding the global ones:
+    /// `,tls`, `,no-tls`, `,h2c`, `,no-h2c` and `,proxy-protocol=MODE`
+    #[arg(long, env, global = true, value_delimiter = ' ', value_parser = parse_listen)]
+    listen: Vec<ListenSpec>,
+
     /// Don't allow json response
     #[arg(short = 'j', long, env, global = true)]
     no_json: bool,
@@ -43,6 +91,367 @@ struct Cli {
     /// Log file location
     #[arg(long, env, global = true)]
     log_file: Option<PathBuf>,
+
+    /// Header to read the token from on /jwt, instead of `Authorization`
+    #[arg(long, env, global = true)]
+    jwt_header: Option<String>,
+
+    /// Cookie to read the token from on /jwt
+    #[arg(long, env, global = true)]
+    jwt_cookie: Option<String>,
+
+    /// HMAC secret used to validate HS256/HS384/HS512 tokens on /jwt
+    #[arg(long, env, global = true)]
+    jwt_secret: Option<String>,
+
+    /// JWKS file used to validate asymmetric tokens on /jwt
+    #[arg(long, env, global = true)]
+    jwt_jwks: Option<PathBuf>,
+
+    /// Enable the mock OAuth2/OIDC provider
+    #[arg(long, env, global = true)]
+    oidc: bool,
+
+    /// JSON file with the clients, users and claims of the mock OIDC provider
+    #[arg(long, env, global = true)]
+    oidc_config: Option<PathBuf>,
+
+    /// Algorithm used to sign the tokens issued by the mock OIDC provider
+    #[arg(long, env, global = true, value_enum, default_value = "rs256")]
+    oidc_algorithm: SigningAlgorithm,
+
+    /// Bearer token granting access to the /_admin routes
+    #[arg(long, env, global = true)]
+    admin_token: Option<String>,
+
+    /// Basic auth user granting access to the /_admin routes, with --admin-password
+    #[arg(long, env, global = true, requires = "admin_password")]
+    admin_user: Option<String>,
+
+    /// Basic auth password granting access to the /_admin routes, with --admin-user
+    #[arg(long, env, global = true, requires = "admin_user")]
+    admin_password: Option<String>,
+
+    /// Serve the /_admin routes on a separate port instead of the service port
+    #[arg(long, env, global = true)]
+    admin_port: Option<u16>,
+
+    /// Host to listen to for the /_admin routes, with --admin-port
+    #[arg(long, env, global = true, default_value = "127.0.0.1")]
+    admin_bind: String,
+
+    /// Set a cookie naming the instance on the first response and report
+    /// whether later requests come back to the same instance
+    #[arg(
+        long,
+        env,
+        global = true,
+        num_args = 0..=1,
+        default_missing_value = "rustwester_instance"
+    )]
+    sticky_cookie: Option<String>,
+
+    /// Content codings used to compress responses, by `Accept-Encoding`
+    #[arg(
+        long,
+        env,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "gzip,deflate,br,zstd"
+    )]
+    compression: Vec<Encoding>,
+
+    /// Don't compress responses
+    #[arg(long, env, global = true)]
+    no_compression: bool,
+
+    /// Origins allowed to make CORS requests, enables CORS. Either `*`, an
+    /// exact origin, a wildcard like `https://*.example.com` or a `~` prefixed regex
+    #[arg(long, env, global = true, value_delimiter = ',')]
+    cors_origin: Vec<String>,
+
+    /// Methods allowed in CORS preflights
+    #[arg(
+        long,
+        env,
+        global = true,
+        value_delimiter = ',',
+        default_value = "GET,POST,PUT,PATCH,DELETE,OPTIONS"
+    )]
+    cors_methods: Vec<String>,
+
+    /// Headers allowed in CORS preflights, any requested header when empty
+    #[arg(long, env, global = true, value_delimiter = ',')]
+    cors_headers: Vec<String>,
+
+    /// Allow CORS requests with credentials
+    #[arg(long, env, global = true)]
+    cors_credentials: bool,
+
+    /// Seconds browsers may cache CORS preflight results
+    #[arg(long, env, global = true)]
+    cors_max_age: Option<u32>,
+
+    /// Directory to serve static files from
+    #[arg(long, env, global = true)]
+    serve_dir: Option<PathBuf>,
+
+    /// Path the --serve-dir directory is mounted under
+    #[arg(long, env, global = true, default_value = "/files")]
+    serve_prefix: String,
+
+    /// Bandwidth limit applied to every response, per second, e.g. `1MiB`
+    #[arg(long, env, global = true, value_parser = parse_rate)]
+    max_bandwidth: Option<u64>,
+
+    /// Proxies, as addresses or CIDRs, trusted to forward the client address
+    /// with `Forwarded`, `X-Forwarded-For` or `X-Real-IP`
+    #[arg(long, env, global = true, value_delimiter = ',', value_parser = parse_trusted_proxy)]
+    trusted_proxies: Vec<ipnet::IpNet>,
+
+    /// Read a PROXY protocol v1/v2 header at the start of each connection,
+    /// its source address becoming the peer address
+    #[arg(long, env, global = true, value_enum, default_value = "off")]
+    proxy_protocol: ProxyProtocolMode,
+
+    /// Accept HTTP/2 without TLS on the service port, with prior knowledge or
+    /// `Upgrade: h2c`
+    #[arg(long, env, global = true)]
+    h2c: bool,
+
+    /// PEM certificate chain to serve HTTPS with on the service port
+    #[arg(long, env, global = true, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of --tls-cert
+    #[arg(long, env, global = true, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Serve HTTP/3 over QUIC with the TLS configuration, advertised with
+    /// `Alt-Svc` on the service port
+    #[arg(long, env, global = true, requires = "tls_cert")]
+    h3: bool,
+
+    /// UDP port of the HTTP/3 listener, the service port by default
+    #[arg(long, env, global = true)]
+    h3_port: Option<u16>,
+
+    /// Port of a gRPC listener serving the health, echo and reflection services. Health stays SERVING
+    /// unless the admin routes are enabled to change it
+    #[arg(long, env, global = true)]
+    grpc_port: Option<u16>,
+
+    /// Port of a raw TCP listener echoing back what it receives
+    #[arg(long, env, global = true)]
+    tcp_echo: Option<u16>,
+
+    /// Port of a UDP listener sending datagrams back to their sender
+    #[arg(long, env, global = true)]
+    udp_echo: Option<u16>,
+
+    /// Prefix the payloads echoed by --tcp-echo and --udp-echo with the
+    /// hostname and the peer address
+    #[arg(long, env, global = true)]
+    echo_prefix: bool,
+
+    /// Time to keep serving, with /readyz failing, after SIGTERM and before
+    /// draining, like a Kubernetes preStop hook, e.g. `10s`
+    #[arg(long, env, global = true, default_value = "0s", value_parser = parse_duration)]
+    shutdown_delay: Duration,
+
+    /// Time requests in flight have to complete once draining, in whole
+    /// seconds
+    #[arg(long, env, global = true, default_value = "30s", value_parser = parse_whole_seconds)]
+    shutdown_timeout: Duration,
+
+    /// Worker threads of the HTTP server [default: number of CPUs]
+    #[arg(long, env, global = true)]
+    workers: Option<usize>,
+
+    /// Connections waiting to be accepted by each listener
+    #[arg(long, env, global = true, default_value_t = server::listener::BACKLOG)]
+    backlog: u32,
+
+    /// Connections served at once by each worker
+    #[arg(long, env, global = true, default_value = "25000")]
+    max_connections: usize,
+
+    /// Time an idle connection is kept open, `0` closing connections after
+    /// each response
+    #[arg(long, env, global = true, default_value = "5s", value_parser = parse_duration)]
+    keep_alive: Duration,
+
+    /// Time a client has to send the head of its first request, `0` to wait
+    /// forever
+    #[arg(long, env, global = true, default_value = "5s", value_parser = parse_duration)]
+    client_request_timeout: Duration,
+
+    /// Time a client has to acknowledge the end of a connection, `0` to wait
+    /// forever
+    #[arg(long, env, global = true, default_value = "0s", value_parser = parse_duration)]
+    client_disconnect_timeout: Duration,
+
+    /// Largest JSON body parsed by `POST /echo`, decoded, e.g. `10MiB`
+    #[arg(long, env, global = true, default_value = "2MiB", value_parser = parse_body_limit)]
+    json_limit: usize,
+
+    /// Largest request body accepted, larger ones being answered 413
+    #[arg(long, env, global = true, value_parser = parse_body_limit)]
+    payload_limit: Option<usize>,
+
+    /// TOML or YAML file holding options by their long name, flags and
+    /// environment variables taking precedence over it
+    #[arg(long, env, global = true)]
+    config: Option<PathBuf>,
+
+    /// Reload the configuration whenever the --config file is modified, as
+    /// on SIGHUP
+    #[arg(long, env, global = true, requires = "config")]
+    watch_config: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, PartialEq)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, PartialEq)]
+enum ConfigCommand {
+    /// Print the effective configuration, secrets redacted
+    Print {
+        /// Format of the configuration
+        #[arg(long, value_enum, default_value = "toml")]
+        format: ConfigFormat,
+    },
+}
+
+/// Options applied again when the configuration is reloaded, the others
+/// needing a restart
+const RELOADABLE: &[&str] = &[
+    "verbose",
+    "no-json",
+    "json-limit",
+    "payload-limit",
+    "max-bandwidth",
+    "trusted-proxies",
+    "sticky-cookie",
+    "cors-origin",
+    "cors-methods",
+    "cors-headers",
+    "cors-credentials",
+    "cors-max-age",
+];
+
+fn log_level(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// The settings of the app following the reloadable options, with the
+/// configuration reported at `/_config`
+fn settings(cli: &Cli, server: &Value, effective: &EffectiveOptions) -> Result<Settings> {
+    let body_limits = BodyLimits {
+        json: cli.json_limit,
+        payload: cli.payload_limit,
+    };
+    let config = json!({
+        "server": server,
+        "body_limits": body_limits.report(),
+        "options": effective.options,
+        "sources": effective.sources,
+    });
+    let cors = match cli.cors_origin.is_empty() {
+        true => None,
+        false => Some(web::Data::new(CorsConfig::new(
+            cli.cors_origin.clone(),
+            cli.cors_methods.clone(),
+            cli.cors_headers.clone(),
+            cli.cors_credentials,
+            cli.cors_max_age,
+        )?)),
+    };
+    Ok(Settings {
+        state: web::Data::new(AppState {
+            allow_json: !cli.no_json,
+        }),
+        body_limits: web::Data::new(body_limits),
+        trusted_proxies: web::Data::new(TrustedProxies(cli.trusted_proxies.clone())),
+        max_bandwidth: cli
+            .max_bandwidth
+            .map(|rate| web::Data::new(MaxBandwidth(rate))),
+        cors,
+        sticky_cookie: cli
+            .sticky_cookie
+            .clone()
+            .map(|name| web::Data::new(StickyCookie(name))),
+        config: web::Data::new(EffectiveConfig(config)),
+    })
+}
+
+/// Read the configuration again and apply the reloadable options that
+/// changed, keeping the current configuration when the new one is invalid
+fn reload(
+    args: &[OsString],
+    current: &Mutex<EffectiveOptions>,
+    reloadable: &Reloadable,
+    server: &Value,
+) {
+    let reloaded = utils::config::parse_from::<Cli, _, _>(args).and_then(|(cli, mut effective)| {
+        let mut current = current.lock().unwrap();
+        let changes = utils::config::changes(&current.options, &effective.options);
+        if changes.is_empty() {
+            info!("Configuration unchanged");
+            return Ok(());
+        }
+        let (applied, ignored): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .partition(|(key, _, _)| RELOADABLE.contains(&key.as_str()));
+        // Reported with the values still in effect
+        for (key, old, new) in &ignored {
+            warn!(
+                "{} changed from {} to {}, restart to apply it",
+                key, old, new
+            );
+            for (options, current) in [
+                (&mut effective.options, &current.options),
+                (&mut effective.sources, &current.sources),
+            ] {
+                match current.get(key) {
+                    Some(value) => options.insert(key.clone(), value.clone()),
+                    None => options.remove(key),
+                };
+            }
+        }
+
+        reloadable.replace(settings(&cli, server, &effective)?);
+        for (key, old, new) in &applied {
+            info!("{} changed from {} to {}", key, old, new);
+            if key == "verbose" {
+                log::set_max_level(log_level(cli.debug));
+            }
+        }
+        *current = effective;
+        Ok(())
+    });
+    if let Err(err) = reloaded {
+        error!("Configuration rejected, keeping the current one: {}", err);
+    }
+}
+
+/// Parse a body size limit, with the units of `parse_size`
+fn parse_body_limit(limit: &str) -> std::result::Result<usize, String> {
+    usize::try_from(parse_size(limit)?).map_err(|_| format!("Limit {} is too large", limit))
 }
 
 struct AppState {
@@ -67,11 +476,68 @@ async fn get_hostname() -> String {
         .unwrap_or("Unknown".to_string())
 }
 
+/// Extract the `User-Agent` header, falling back to "Unknown"
+fn user_agent(req: &HttpRequest) -> &str {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("Unknown")
+}
+
+/// Decide whether to answer with JSON, based on the `?json` query parameter
+/// and the `Accept` header, unless JSON responses are disabled
+fn wants_json(req: &HttpRequest, json_param: bool, data: &AppState) -> bool {
+    // Get the 'Accept' header from the request
+    let accept_header = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok());
+    debug!("Accept header: {:?}", accept_header);
+
+    data.allow_json && (json_param || accept_header.is_some_and(|v| v.contains("application/json")))
+}
+
+/// Extra sections attached to a request by middlewares and handlers, added to
+/// the output of `prepare_response`
+#[derive(Default)]
+struct ResponseDetails {
+    sections: Map<String, Value>,
+    /// Sections describing the connection rather than the content, which
+    /// differ between identical requests and are left out of the `ETag`
+    per_connection: HashSet<String>,
+}
+
+fn insert_detail(req: &HttpRequest, name: &str, value: Value, per_connection: bool) {
+    let mut extensions = req.extensions_mut();
+    if !extensions.contains::<ResponseDetails>() {
+        extensions.insert(ResponseDetails::default());
+    }
+    if let Some(details) = extensions.get_mut::<ResponseDetails>() {
+        details.sections.insert(name.to_string(), value);
+        match per_connection {
+            true => details.per_connection.insert(name.to_string()),
+            false => details.per_connection.remove(name),
+        };
+    }
+}
+
+/// Attach a named section to the response rendered for this request
+fn add_detail(req: &HttpRequest, name: &str, value: Value) {
+    insert_detail(req, name, value, false);
+}
+
+/// Attach a named section describing the connection of this request, which
+/// leaves the `ETag` of the response unchanged
+fn add_connection_detail(req: &HttpRequest, name: &str, value: Value) {
+    insert_detail(req, name, value, true);
+}
+
 async fn render_markup(
     hostname: &str,
     user_agent: &str,
     hello_str: Option<&str>,
     echo_str: Option<Value>,
+    details: &Map<String, Value>,
 ) -> Markup {
     html! {
         (DOCTYPE)
@@ -88,30 +554,78 @@ async fn render_markup(
             } @else {
                 hr;
             }
+            @for (name, value) in details {
+                h2 { (name) }
+                pre { (format!("{:#}", value)) }
+            }
         }
     }
 }
 
+/// Entity tag derived from `content`, identical content gets the same tag. It
+/// is weak when `content` leaves out parts of the body, whose bytes then differ
+/// under the same tag
+fn etag(content: &[u8], weak: bool) -> header::EntityTag {
+    let digest = Sha256::digest(content);
+    let tag = digest[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    header::EntityTag::new(weak, tag)
+}
+
 async fn prepare_response(
+    req: &HttpRequest,
     json: bool,
-    user_agent: &str,
     hello_str: Option<&str>,
     echo_str: Option<Value>,
-) -> impl Responder {
+) -> HttpResponse {
     let hostname = get_hostname().await;
+    let user_agent = user_agent(req);
+    let (details, per_connection) = req
+        .extensions()
+        .get::<ResponseDetails>()
+        .map(|d| (d.sections.clone(), d.per_connection.clone()))
+        .unwrap_or_default();
+
+    // The tag covers what every identical request gets, in either format,
+    // without the details of its connection
+    let mut content: Map<String, Value> = details
+        .iter()
+        .filter(|(name, _)| !per_connection.contains(*name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    content.insert(
+        "format".to_string(),
+        json!(if json { "json" } else { "html" }),
+    );
+    content.insert("response".to_string(), json!(echo_str));
+    content.insert("hello".to_string(), json!(hello_str));
+    content.insert("hostname".to_string(), json!(hostname));
+    content.insert("user_agent".to_string(), json!(user_agent));
+    let excluded = details.keys().any(|name| per_connection.contains(name));
+    let etag = etag(&serde_json::to_vec(&content).unwrap_or_default(), excluded);
+
     if json {
         debug!("Returning JSON response");
-        let json_response = json!({
-            "response": echo_str.unwrap_or(json!(hello_str.unwrap_or("Hello world"))),
-            "hostname": hostname,
-            "user_agent": user_agent
-        });
-        HttpResponse::Ok().json(json_response)
+        let mut json_response = details;
+        json_response.insert(
+            "response".to_string(),
+            echo_str.unwrap_or(json!(hello_str.unwrap_or("Hello world"))),
+        );
+        json_response.insert("hostname".to_string(), json!(hostname));
+        json_response.insert("user_agent".to_string(), json!(user_agent));
+        HttpResponse::Ok()
+            .insert_header(header::ContentType::json())
+            .insert_header((header::ETAG, etag))
+            .json(json_response)
     } else {
         debug!("Returning HTML response");
-        let html_response = render_markup(&hostname, user_agent, hello_str, echo_str).await;
+        let html_response =
+            render_markup(&hostname, user_agent, hello_str, echo_str, &details).await;
         HttpResponse::Ok()
             .append_header(header::ContentType::html())
+            .insert_header((header::ETAG, etag))
             .body(html_response.into_string())
     }
 }
@@ -122,24 +636,9 @@ async fn hello(
     info: web::Query<RequestInfo>,
     data: web::Data<AppState>,
 ) -> impl Responder {
-    let user_agent = req
-        .headers()
-        .get(header::USER_AGENT)
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("Unknown");
-
-    // Get the 'Accept' header from the request
-    let accept_header = req
-        .headers()
-        .get(header::ACCEPT)
-        .and_then(|v| v.to_str().ok());
-    debug!("Accept header: {:?}", accept_header);
-
     prepare_response(
-        data.allow_json
-            && (info.json.is_some()
-                || accept_header.is_some_and(|v| v.contains("application/json"))),
-        user_agent,
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
         None,
         None,
     )
@@ -203,29 +702,15 @@ async fn echo_form() -> impl Responder {
 async fn echo(
     req: HttpRequest,
     info: web::Query<RequestInfo>,
-    req_body: web::Json<serde_json::Value>,
+    payload: web::Payload,
     data: web::Data<AppState>,
-) -> Result<impl Responder> {
-    let user_agent = req
-        .headers()
-        .get(header::USER_AGENT)
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("Unknown");
-
-    let parsed = req_body.into_inner();
-
-    // Get the 'Accept' header from the request
-    let accept_header = req
-        .headers()
-        .get(header::ACCEPT)
-        .and_then(|v| v.to_str().ok());
-    debug!("Accept header: {:?}", accept_header);
+) -> actix_web::Result<impl Responder> {
+    let (parsed, body) = routes::compression::read_json_body(&req, payload).await?;
+    add_detail(&req, "body", body);
 
     Ok(prepare_response(
-        data.allow_json
-            && (info.json.is_some()
-                || accept_header.is_some_and(|v| v.contains("application/json"))),
-        user_agent,
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
         None,
         Some(parsed),
     )
@@ -237,73 +722,341 @@ async fn manual_hello(
     info: web::Query<RequestInfo>,
     data: web::Data<AppState>,
 ) -> impl Responder {
-    let user_agent = req
-        .headers()
-        .get(http::header::USER_AGENT)
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("Unknown");
-
-    // Get the 'Accept' header from the request
-    let accept_header = req
-        .headers()
-        .get(http::header::ACCEPT)
-        .and_then(|v| v.to_str().ok());
-    debug!("Accept header: {:?}", accept_header);
-
     prepare_response(
-        data.allow_json
-            && (info.json.is_some()
-                || accept_header.is_some_and(|v| v.contains("application/json"))),
-        user_agent,
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
         Some("Hey there!"),
         None,
     )
     .await
 }
 
+/// Wrap `app` in the middlewares of the service, from the innermost to the
+/// outermost
+fn wrap_middlewares<T, B>(
+    app: App<T>,
+    compression: bool,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+>
+where
+    T: ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<B>,
+            Error = actix_web::Error,
+            InitError = (),
+        > + 'static,
+    B: MessageBody + 'static,
+{
+    app.wrap(
+        DefaultHeaders::new()
+            .add(("X-Version", crate_version!()))
+            .add((header::SERVER, "rustwester"))
+            .add(("X-Powered-By", "actix-web")),
+    )
+    .wrap(from_fn(alt_svc))
+    .wrap(from_fn(sticky_session))
+    .wrap(Condition::new(compression, Compress::default()))
+    .wrap(from_fn(weaken_encoded_etag))
+    .wrap(from_fn(filter_accept_encoding))
+    .wrap(from_fn(cors))
+    .wrap(from_fn(limit_bandwidth))
+    .wrap(from_fn(limit_payload))
+    .wrap(from_fn(client_ip))
+    .wrap(from_fn(proxy_protocol))
+    .wrap(from_fn(connection))
+    .wrap(access_logger())
+    .wrap(from_fn(track_in_flight))
+    .wrap(from_fn(apply_settings))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let (cli, effective) = utils::config::parse::<Cli>()?;
+
+    if let Some(Command::Config {
+        action: ConfigCommand::Print { format },
+    }) = cli.command
+    {
+        print!("{}", effective.render(format)?);
+        return Ok(());
+    }
 
     // Initialize the logger
-    let log_level = match cli.debug {
-        0 => LevelFilter::Info,
-        1 => LevelFilter::Debug,
-        _ => LevelFilter::Trace,
-    };
+    let log_level = log_level(cli.debug);
 
-    log_init(log_level, cli.use_json_logging, cli.log_file)?;
+    log_init(log_level, cli.use_json_logging, cli.log_file.clone())?;
 
     info!("Starting up...");
     if log_level > LevelFilter::Info {
         info!("Debugging enabled to level {}", log_level);
     }
 
-    info!("Starting server on {}:{}", cli.bind, cli.port);
-
-    // Clone cli.json to move it into the closure
-    let json_data = !cli.no_json;
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(AppState {
-                allow_json: json_data,
-            }))
-            .wrap(
-                DefaultHeaders::new()
-                    .add(("X-Version", crate_version!()))
-                    .add((header::SERVER, "rustwester"))
-                    .add(("X-Powered-By", "actix-web")),
+    let server_options = ServerOptions {
+        workers: cli.workers.unwrap_or(ServerOptions::default().workers),
+        backlog: cli.backlog,
+        max_connections: cli.max_connections,
+        keep_alive: cli.keep_alive,
+        client_request_timeout: cli.client_request_timeout,
+        client_disconnect_timeout: cli.client_disconnect_timeout,
+        shutdown_timeout: cli.shutdown_timeout,
+    };
+    let server_report = server_options.report();
+    let settings = settings(&cli, &server_report, &effective)?;
+    let effective_config = settings.config.clone();
+    let reloadable = web::Data::new(Reloadable::new(settings));
+    let jwt_config = web::Data::new(JwtConfig::new(
+        cli.jwt_header,
+        cli.jwt_cookie,
+        cli.jwt_secret,
+        cli.jwt_jwks.as_deref(),
+    )?);
+    let oidc_provider = if cli.oidc {
+        let oidc_config = match &cli.oidc_config {
+            Some(path) => OidcConfig::from_file(path)?,
+            None => OidcConfig::default(),
+        };
+        info!("Mock OIDC provider enabled");
+        Some(web::Data::new(OidcProvider::new(
+            oidc_config,
+            cli.oidc_algorithm,
+        )?))
+    } else {
+        None
+    };
+
+    // Shared by the app and the shutdown of the server
+    let lifecycle = web::Data::new(Lifecycle::default());
+    let app_lifecycle = lifecycle.clone();
+    let app_reloadable = reloadable.clone();
+    if let Some(name) = &cli.sticky_cookie {
+        info!("Sticky session check enabled with cookie {}", name);
+    }
+    if !cli.cors_origin.is_empty() {
+        info!("CORS enabled for {:?}", cli.cors_origin);
+    }
+    let serve_dir = match cli.serve_dir {
+        Some(dir) => {
+            let serve_dir = ServeDir::new(dir, &cli.serve_prefix)?;
+            info!(
+                "Serving {} under {}",
+                serve_dir.dir.display(),
+                serve_dir.prefix
+            );
+            Some(serve_dir)
+        }
+        None => None,
+    };
+    if !cli.trusted_proxies.is_empty() {
+        info!("Trusting forwarding headers from {:?}", cli.trusted_proxies);
+    }
+    if let Some(rate) = cli.max_bandwidth {
+        info!("Responses limited to {} bytes per second", rate);
+    }
+    let compression = !cli.no_compression && !cli.compression.is_empty();
+    if compression {
+        info!("Response compression enabled with {:?}", cli.compression);
+    }
+    let compression_config = web::Data::new(CompressionConfig(cli.compression));
+    let grpc_health = cli.grpc_port.map(|_| HealthReporter::new());
+    let admin_state = web::Data::new(AdminState {
+        grpc_health: grpc_health.clone(),
+        ..AdminState::new(cli.admin_token, cli.admin_user, cli.admin_password)
+    });
+    let admin_on_main = admin_state.is_enabled() && cli.admin_port.is_none();
+    if !admin_state.is_enabled() {
+        info!("Admin routes disabled, set --admin-token or --admin-user/--admin-password");
+    }
+
+    let admin_server = match cli.admin_port {
+        Some(port) if admin_state.is_enabled() => {
+            info!("Starting admin server on {}:{}", cli.admin_bind, port);
+            let admin_state = admin_state.clone();
+            Some(
+                HttpServer::new(move || {
+                    App::new()
+                        .wrap(Logger::default())
+                        .configure(|cfg| routes::admin::configure(cfg, admin_state.clone()))
+                })
+                .workers(1)
+                .shutdown_timeout(server_options.shutdown_timeout_secs())
+                .disable_signals()
+                .bind((cli.admin_bind, port))?
+                .run(),
             )
-            .wrap(Logger::default())
+        }
+        _ => None,
+    };
+
+    let tls = match (&cli.tls_cert, &cli.tls_key) {
+        (Some(cert), Some(key)) => Some(server::tls::load(cert, key)?),
+        _ => None,
+    };
+    let options = ListenerOptions {
+        proxy_protocol: cli.proxy_protocol,
+        h2c: cli.h2c,
+        tls: tls
+            .as_ref()
+            .map(|tls| server::tls::with_alpn(tls, &[b"h2", b"http/1.1"])),
+    };
+    // Sockets passed by systemd replace --bind and --port, unless --listen
+    // picks them by name
+    let inherited = server::systemd::listen_fds()?;
+    let specs = match (cli.listen.is_empty(), inherited.is_empty()) {
+        (false, _) => cli.listen,
+        (true, false) => {
+            let mut specs: Vec<ListenSpec> = Vec::new();
+            for (name, _) in &inherited {
+                let spec = ListenSpec::new(ListenAddress::Fd(name.clone()));
+                if !specs.contains(&spec) {
+                    specs.push(spec);
+                }
+            }
+            specs
+        }
+        (true, true) => vec![ListenSpec::new(ListenAddress::Tcp(
+            match cli.bind.parse::<IpAddr>() {
+                Ok(addr) => SocketAddr::new(addr, cli.port).to_string(),
+                Err(_) => format!("{}:{}", cli.bind, cli.port),
+            },
+        ))],
+    };
+    let listeners = server::listener::bind(&specs, &options, cli.backlog, inherited)?;
+    for listener in &listeners {
+        info!("Listening on {} with {:?}", listener.name, listener.options);
+    }
+    // HTTP/3 is served next to the first TLS listener
+    let h3_addr = match cli.h3 {
+        true => listeners
+            .iter()
+            .filter(|listener| listener.options.tls.is_some())
+            .find_map(|listener| listener.local_addr())
+            .map(|addr| SocketAddr::new(addr.ip(), cli.h3_port.unwrap_or(addr.port())))
+            .ok_or_else(|| WesterError::Other("--h3 needs a TCP listener with TLS".to_string()))
+            .map(Some)?,
+        false => None,
+    };
+    let h3_alt_svc = h3_addr.map(|addr| web::Data::new(AltSvc::new(addr.port())));
+
+    let app = move || {
+        let app = App::new()
+            .app_data(app_reloadable.clone())
+            .app_data(jwt_config.clone())
+            .app_data(compression_config.clone());
+        wrap_middlewares(app, compression)
             .service(hello)
             .service(echo)
             .service(echo_form)
+            .service(jwt_inspect)
+            .service(ip)
+            .service(get_cookies)
+            .service(set_cookies)
+            .service(delete_cookies)
+            .service(cache)
+            .service(cache_for)
+            .service(range)
+            .service(upload)
+            .service(download)
+            .service(gzip_body)
+            .service(deflate_body)
+            .service(brotli_body)
+            .service(zstd_body)
             .route("/hey", web::get().to(manual_hello))
-    })
-    .bind((cli.bind, cli.port))?
-    .run()
-    .await?;
+            .configure(routes::redirect::configure)
+            .configure(routes::cors::configure)
+            .configure(|cfg| routes::lifecycle::configure(cfg, app_lifecycle.clone()))
+            .configure(|cfg| routes::config::configure(cfg, effective_config.clone()))
+            .configure(|cfg| {
+                if let Some(alt_svc) = &h3_alt_svc {
+                    cfg.app_data(alt_svc.clone());
+                }
+                if let Some(provider) = &oidc_provider {
+                    cfg.app_data(provider.clone());
+                    routes::oidc::configure(cfg);
+                }
+                if admin_on_main {
+                    routes::admin::configure(cfg, admin_state.clone());
+                }
+                // Registered last, so a `/` prefix doesn't shadow the other routes
+                if let Some(serve_dir) = &serve_dir {
+                    routes::files::configure(cfg, serve_dir);
+                }
+            })
+    };
+    let h3_server = match (&tls, h3_addr) {
+        (Some(tls), Some(addr)) => {
+            let (_, h3_server) = server::h3::serve(app.clone(), addr, tls)?;
+            Some(h3_server)
+        }
+        _ => None,
+    };
+    // Servers that don't handle signals, and stop with the HTTP ones
+    let mut other_servers: Vec<BoxFuture<'static, std::io::Result<()>>> = Vec::new();
+    if let (Some(port), Some(health)) = (cli.grpc_port, grpc_health) {
+        let listener = std::net::TcpListener::bind((cli.bind.as_str(), port))?;
+        other_servers.push(server::grpc::serve(listener, health)?.boxed());
+    }
+    if let Some(port) = cli.tcp_echo {
+        let listener = std::net::TcpListener::bind((cli.bind.as_str(), port))?;
+        other_servers.push(server::echo::tcp(listener, cli.echo_prefix)?.boxed());
+    }
+    if let Some(port) = cli.udp_echo {
+        let socket = std::net::UdpSocket::bind((cli.bind.as_str(), port))?;
+        other_servers.push(server::echo::udp(socket, cli.echo_prefix)?.boxed());
+    }
+    let server = server::serve(app, listeners, &server_options)?;
+    let mut handles = vec![server.handle()];
+    handles.extend(
+        admin_server
+            .as_ref()
+            .map(|admin_server| admin_server.handle()),
+    );
+    let shutdown = server::shutdown::on_signal(handles, lifecycle.clone(), cli.shutdown_delay)?;
+    tokio::spawn(shutdown);
+    let current = Mutex::new(effective);
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let reload = server::reload::on_reload(cli.config.filter(|_| cli.watch_config), move || {
+        reload(&args, &current, &reloadable, &server_report)
+    })?;
+    tokio::spawn(reload);
+    server::systemd::notify(&[NotifyState::Ready]);
+    tokio::spawn(server::systemd::watchdog());
+    let server = async {
+        match admin_server {
+            Some(admin_server) => tokio::try_join!(server, admin_server).map(|_| ()),
+            None => server.await,
+        }
+    };
+    let server = async {
+        match other_servers.is_empty() {
+            true => server.await,
+            false => tokio::select! {
+                res = server => res,
+                res = try_join_all(other_servers) => res.map(|_| ()),
+            },
+        }
+    };
+
+    match h3_server {
+        // The QUIC listener runs its app on this thread, and stops with the
+        // TCP listener
+        Some(h3_server) => {
+            let local = tokio::task::LocalSet::new();
+            tokio::select! {
+                res = server => res?,
+                res = local.run_until(h3_server) => res?,
+            }
+        }
+        None => server.await?,
+    }
+    info!("Stopped with {} requests in flight", lifecycle.in_flight());
 
     Ok(())
 }
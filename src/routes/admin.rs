@@ -0,0 +1,213 @@
+use crate::get_hostname;
+use crate::server::grpc::parse_serving_status;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{web, Error, HttpResponse, Responder};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use clap::crate_version;
+use log::{info, warn, LevelFilter};
+use serde::Deserialize;
+use serde_json::json;
+use tonic_health::server::HealthReporter;
+
+/// Credentials guarding the `/_admin` scope
+pub struct AdminState {
+    pub token: Option<String>,
+    pub basic: Option<(String, String)>,
+    pub started_at: DateTime<Utc>,
+    /// Health of the gRPC listener, when there is one
+    pub grpc_health: Option<HealthReporter>,
+}
+
+impl AdminState {
+    pub fn new(token: Option<String>, user: Option<String>, password: Option<String>) -> Self {
+        Self {
+            token,
+            basic: user.zip(password),
+            started_at: Utc::now(),
+            grpc_health: None,
+        }
+    }
+
+    /// The admin scope is only served when some credentials are configured
+    pub fn is_enabled(&self) -> bool {
+        self.token.is_some() || self.basic.is_some()
+    }
+
+    fn check(&self, authorization: Option<&str>) -> Result<(), &'static str> {
+        let Some(authorization) = authorization else {
+            return Err("missing credentials");
+        };
+        let (scheme, value) = authorization
+            .split_once(' ')
+            .ok_or("malformed Authorization header")?;
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return match &self.token {
+                Some(token) if constant_time_eq(token.as_bytes(), value.trim().as_bytes()) => {
+                    Ok(())
+                }
+                Some(_) => Err("invalid token"),
+                None => Err("token authentication is not enabled"),
+            };
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let Some((user, password)) = &self.basic else {
+                return Err("basic authentication is not enabled");
+            };
+            let decoded = STANDARD
+                .decode(value.trim())
+                .ok()
+                .and_then(|v| String::from_utf8(v).ok())
+                .ok_or("malformed basic credentials")?;
+            let (given_user, given_password) = decoded
+                .split_once(':')
+                .ok_or("malformed basic credentials")?;
+            // Compare both parts to avoid leaking which one was wrong
+            let user_ok = constant_time_eq(user.as_bytes(), given_user.as_bytes());
+            let password_ok = constant_time_eq(password.as_bytes(), given_password.as_bytes());
+            return if user_ok && password_ok {
+                Ok(())
+            } else {
+                Err("invalid username or password")
+            };
+        }
+
+        Err("unsupported authentication scheme")
+    }
+
+    fn challenge(&self) -> &'static str {
+        if self.basic.is_some() {
+            "Basic realm=\"rustwester admin\""
+        } else {
+            "Bearer realm=\"rustwester admin\""
+        }
+    }
+}
+
+/// Compare secrets in a time that doesn't depend on where they differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Reject requests without valid admin credentials, logging every failure
+async fn require_admin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let state = req
+        .app_data::<web::Data<AdminState>>()
+        .expect("AdminState is registered with the admin scope")
+        .clone();
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+
+    match state.check(authorization) {
+        Ok(()) => next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body),
+        Err(reason) => {
+            warn!(
+                "Rejected admin request {} {} from {}: {}",
+                req.method(),
+                req.path(),
+                req.peer_addr()
+                    .map_or("unknown".to_string(), |a| a.ip().to_string()),
+                reason
+            );
+            let response = HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, state.challenge()))
+                .finish();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+async fn status(state: web::Data<AdminState>) -> impl Responder {
+    let now = Utc::now();
+    HttpResponse::Ok().json(json!({
+        "version": crate_version!(),
+        "hostname": get_hostname().await,
+        "pid": std::process::id(),
+        "started_at": state.started_at.to_rfc3339(),
+        "uptime_seconds": (now - state.started_at).num_seconds(),
+        "log_level": log::max_level().to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct LogLevelQuery {
+    level: String,
+}
+
+/// Change the log level at runtime, e.g. `PUT /_admin/log-level?level=debug`
+async fn set_log_level(query: web::Query<LogLevelQuery>) -> impl Responder {
+    let Ok(level) = query.level.parse::<LevelFilter>() else {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("Unknown log level {}", query.level),
+        }));
+    };
+
+    let previous = log::max_level();
+    log::set_max_level(level);
+    info!("Log level changed from {} to {}", previous, level);
+
+    HttpResponse::Ok().json(json!({
+        "previous": previous.to_string(),
+        "log_level": level.to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct GrpcHealthQuery {
+    status: String,
+    #[serde(default)]
+    service: String,
+}
+
+/// Change the status reported by the gRPC health service, of the whole server
+/// or of one service, e.g. `PUT /_admin/grpc-health?status=not_serving`
+async fn set_grpc_health(
+    state: web::Data<AdminState>,
+    query: web::Query<GrpcHealthQuery>,
+) -> impl Responder {
+    let Some(health) = &state.grpc_health else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "The gRPC listener is not enabled, set --grpc-port",
+        }));
+    };
+    let Some(status) = parse_serving_status(&query.status) else {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("Unknown health status {}", query.status),
+        }));
+    };
+
+    health.set_service_status(&query.service, status).await;
+    info!("gRPC health of {:?} set to {}", query.service, status);
+
+    HttpResponse::Ok().json(json!({
+        "service": query.service,
+        "status": status.to_string(),
+    }))
+}
+
+/// Register the `/_admin` scope behind the authentication middleware
+pub fn configure(cfg: &mut web::ServiceConfig, state: web::Data<AdminState>) {
+    cfg.service(
+        web::scope("/_admin")
+            .app_data(state)
+            .wrap(from_fn(require_admin))
+            .route("", web::get().to(status))
+            .route("/", web::get().to(status))
+            .route("/log-level", web::put().to(set_log_level))
+            .route("/grpc-health", web::put().to(set_grpc_health)),
+    );
+}
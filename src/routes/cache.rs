@@ -0,0 +1,139 @@
+use crate::{prepare_response, wants_json, AppState, RequestInfo};
+use actix_web::http::header::{
+    self, CacheControl, CacheDirective, EntityTag, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch,
+    IfUnmodifiedSince, LastModified, TryIntoHeaderValue,
+};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{get, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use log::debug;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Content served by `/cache` only changes with the server, so it is
+/// considered modified when the server started
+pub static LAST_MODIFIED: LazyLock<SystemTime> = LazyLock::new(|| {
+    // HTTP dates have a one second precision
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(now.as_secs())
+});
+
+/// Evaluate the conditional request headers in the order of RFC 9110,
+/// returning the status to answer with when a precondition decides it
+fn evaluate_preconditions(
+    req: &HttpRequest,
+    etag: Option<&EntityTag>,
+    last_modified: SystemTime,
+) -> Option<StatusCode> {
+    let safe = req.method() == Method::GET || req.method() == Method::HEAD;
+
+    match req.get_header::<IfMatch>() {
+        Some(IfMatch::Any) => {}
+        Some(IfMatch::Items(tags)) => {
+            if !etag.is_some_and(|etag| tags.iter().any(|t| t.strong_eq(etag))) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        }
+        None => {
+            if let Some(IfUnmodifiedSince(since)) = req.get_header::<IfUnmodifiedSince>() {
+                if last_modified > SystemTime::from(since) {
+                    return Some(StatusCode::PRECONDITION_FAILED);
+                }
+            }
+        }
+    }
+
+    let failed = if safe {
+        StatusCode::NOT_MODIFIED
+    } else {
+        StatusCode::PRECONDITION_FAILED
+    };
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => return Some(failed),
+        Some(IfNoneMatch::Items(tags)) => {
+            if etag.is_some_and(|etag| tags.iter().any(|t| t.weak_eq(etag))) {
+                return Some(failed);
+            }
+        }
+        None => {
+            if let Some(IfModifiedSince(since)) = req.get_header::<IfModifiedSince>() {
+                if safe && last_modified <= SystemTime::from(since) {
+                    return Some(StatusCode::NOT_MODIFIED);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+async fn cache_response(req: &HttpRequest, json: bool, max_age: Option<u32>) -> HttpResponse {
+    let mut response = prepare_response(req, json, Some("Cache"), None).await;
+    let last_modified = *LAST_MODIFIED;
+    let etag = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<EntityTag>().ok());
+
+    let headers = response.headers_mut();
+    if let Ok(value) = LastModified(HttpDate::from(last_modified)).try_into_value() {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    if let Some(max_age) = max_age {
+        let cache_control = CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(max_age),
+        ]);
+        if let Ok(value) = cache_control.try_into_value() {
+            headers.insert(header::CACHE_CONTROL, value);
+        }
+    }
+
+    match evaluate_preconditions(req, etag.as_ref(), last_modified) {
+        Some(StatusCode::NOT_MODIFIED) => {
+            debug!("Conditional request answered with 304 Not Modified");
+            // A 304 keeps the validators and caching headers, without a body
+            let mut not_modified = HttpResponse::NotModified();
+            for name in [header::ETAG, header::LAST_MODIFIED, header::CACHE_CONTROL] {
+                if let Some(value) = response.headers().get(&name) {
+                    not_modified.insert_header((name, value.clone()));
+                }
+            }
+            not_modified.finish()
+        }
+        Some(status) => {
+            debug!("Conditional request answered with {}", status);
+            HttpResponse::new(status)
+        }
+        None => response,
+    }
+}
+
+/// Answer with `ETag` and `Last-Modified`, honouring `If-None-Match`,
+/// `If-Modified-Since`, `If-Match` and `If-Unmodified-Since`
+#[get("/cache")]
+pub async fn cache(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    cache_response(&req, wants_json(&req, info.json.is_some(), &data), None).await
+}
+
+/// Same as `/cache`, with `Cache-Control: public, max-age=<seconds>`
+#[get("/cache/{seconds}")]
+pub async fn cache_for(
+    req: HttpRequest,
+    seconds: web::Path<u32>,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    cache_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Some(seconds.into_inner()),
+    )
+    .await
+}
@@ -0,0 +1,198 @@
+use crate::{add_connection_detail, prepare_response, wants_json, AppState, RequestInfo};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName};
+use actix_web::middleware::{Logger, Next};
+use actix_web::{get, web, Error, HttpRequest, Responder};
+use ipnet::IpNet;
+use log::debug;
+use serde_json::{json, Map, Value};
+use std::net::{IpAddr, SocketAddr};
+
+/// Proxies allowed to tell the client address through forwarding headers
+#[derive(Default)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+/// Parse a trusted proxy given either as a CIDR or as a single address
+pub fn parse_trusted_proxy(proxy: &str) -> Result<IpNet, String> {
+    proxy
+        .parse::<IpNet>()
+        .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid proxy address or CIDR {}", proxy))
+}
+
+impl TrustedProxies {
+    fn contains(&self, addr: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(addr))
+    }
+}
+
+/// Client address derived from the peer address and forwarding headers
+pub struct ClientIp {
+    pub client_ip: String,
+    pub source: &'static str,
+    pub peer: Option<SocketAddr>,
+    pub peer_trusted: bool,
+    pub chain: Vec<String>,
+    pub forwarded: Vec<Map<String, Value>>,
+    pub x_forwarded_for: Vec<String>,
+    pub x_real_ip: Option<String>,
+}
+
+impl ClientIp {
+    /// Walk the forwarding chain from the peer towards the client, as long as
+    /// hops are trusted proxies. Headers sent by untrusted peers are ignored
+    pub fn resolve(req: &HttpRequest, trusted: &TrustedProxies) -> Self {
+        let peer = req.peer_addr();
+        let forwarded = parse_forwarded(req);
+        let x_forwarded_for = header_list(req, header::X_FORWARDED_FOR);
+        let x_real_ip = req
+            .headers()
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string());
+        let peer_trusted = peer.is_some_and(|p| trusted.contains(&p.ip()));
+
+        let forwarded_for: Vec<String> = forwarded
+            .iter()
+            .filter_map(|element| element.get("for").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect();
+        let (source, hops) = if !peer_trusted {
+            ("peer", Vec::new())
+        } else if !forwarded_for.is_empty() {
+            ("forwarded", forwarded_for)
+        } else if !x_forwarded_for.is_empty() {
+            ("x-forwarded-for", x_forwarded_for.clone())
+        } else if let Some(x_real_ip) = &x_real_ip {
+            ("x-real-ip", vec![x_real_ip.clone()])
+        } else {
+            ("peer", Vec::new())
+        };
+
+        let peer_ip = peer.map_or("unknown".to_string(), |p| p.ip().to_string());
+        let mut client_ip = peer_ip.clone();
+        for hop in hops.iter().rev() {
+            client_ip = node_ip(hop).map_or(hop.clone(), |addr| addr.to_string());
+            if !node_ip(hop).is_some_and(|addr| trusted.contains(&addr)) {
+                break;
+            }
+        }
+        let mut chain = hops;
+        chain.push(peer_ip);
+
+        Self {
+            client_ip,
+            source,
+            peer,
+            peer_trusted,
+            chain,
+            forwarded,
+            x_forwarded_for,
+            x_real_ip,
+        }
+    }
+
+    pub fn report(&self) -> Value {
+        json!({
+            "client_ip": self.client_ip,
+            "source": self.source,
+            "peer": self.peer.map(|p| p.to_string()),
+            "peer_trusted": self.peer_trusted,
+            "chain": self.chain,
+            "forwarded": self.forwarded,
+            "x_forwarded_for": self.x_forwarded_for,
+            "x_real_ip": self.x_real_ip,
+        })
+    }
+}
+
+fn header_list(req: &HttpRequest, name: HeaderName) -> Vec<String> {
+    req.headers()
+        .get_all(name)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Parse the RFC 7239 `Forwarded` header into its elements, e.g.
+/// `for=192.0.2.60;proto=http;by=203.0.113.43, for="[2001:db8::17]:4711"`
+fn parse_forwarded(req: &HttpRequest) -> Vec<Map<String, Value>> {
+    header_list(req, header::FORWARDED)
+        .iter()
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| {
+                    (
+                        key.trim().to_ascii_lowercase(),
+                        json!(value.trim().trim_matches('"')),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// IP of a forwarded node, which may carry a port, be bracketed, or be
+/// `unknown` or an obfuscated identifier
+fn node_ip(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            node.trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+                .ok()
+        })
+}
+
+fn resolve(req: &HttpRequest) -> ClientIp {
+    match req.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted) => ClientIp::resolve(req, trusted),
+        None => ClientIp::resolve(req, &TrustedProxies::default()),
+    }
+}
+
+/// Add the client address to every response rendered by `prepare_response`
+pub async fn client_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let client = resolve(req.request());
+    debug!(
+        "Client IP {} from {}, chain {:?}",
+        client.client_ip, client.source, client.chain
+    );
+    add_connection_detail(req.request(), "client", client.report());
+
+    next.call(req).await
+}
+
+/// The default access log format, with the resolved client IP instead of
+/// the peer address
+pub fn access_logger() -> Logger {
+    Logger::new("%{client_ip}xi \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T")
+        .custom_request_replace("client_ip", |req| resolve(req.request()).client_ip)
+}
+
+#[get("/ip")]
+pub async fn ip(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let client = resolve(&req);
+
+    prepare_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Some(&format!("Your IP is {}", client.client_ip)),
+        Some(client.report()),
+    )
+    .await
+}
@@ -0,0 +1,326 @@
+use crate::routes::limits::{BodyLimits, DEFAULT_JSON_LIMIT};
+use crate::{prepare_response, wants_json, AppState, RequestInfo};
+use actix_web::body::{self, MessageBody};
+use actix_web::dev::{Decompress, ServiceRequest, ServiceResponse};
+use actix_web::error::JsonPayloadError;
+use actix_web::http::header::{
+    self, EntityTag, HeaderMap, HeaderValue, IfNoneMatch, TryIntoHeaderValue,
+};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{get, mime, web, Error, HttpMessage, HttpRequest, HttpResponse, Responder};
+use brotli::CompressorWriter;
+use clap::ValueEnum;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use futures_util::StreamExt;
+use log::debug;
+use serde_json::{json, Map, Value};
+use std::cell::Cell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Content codings the compression middleware may answer with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    #[value(alias = "brotli")]
+    Br,
+    Zstd,
+}
+
+impl Encoding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Br => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// Content codings enabled with `--compression`
+pub struct CompressionConfig(pub Vec<Encoding>);
+
+/// Drop the codings that are not enabled from `Accept-Encoding`, so the
+/// `Compress` middleware only negotiates between the enabled ones
+pub async fn filter_accept_encoding(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(config) = req.app_data::<web::Data<CompressionConfig>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    if let Some(accept_encoding) = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    {
+        let allowed: Vec<&str> = accept_encoding
+            .split(',')
+            .map(str::trim)
+            .filter(|item| {
+                let coding = item.split(';').next().unwrap_or_default().trim();
+                coding.eq_ignore_ascii_case("identity")
+                    || config
+                        .0
+                        .iter()
+                        .any(|e| coding.eq_ignore_ascii_case(e.as_str()))
+            })
+            .collect();
+        debug!(
+            "Accept-Encoding {:?} filtered to {:?}",
+            accept_encoding, allowed
+        );
+
+        match HeaderValue::from_str(&allowed.join(", ")) {
+            Ok(value) if !allowed.is_empty() => {
+                req.headers_mut().insert(header::ACCEPT_ENCODING, value);
+            }
+            _ => {
+                req.headers_mut().remove(header::ACCEPT_ENCODING);
+            }
+        }
+    }
+
+    next.call(req).await
+}
+
+/// Make the `ETag` of a content-coded response weak, its bytes differing from
+/// those of the identity representation the tag was computed for
+fn weaken_etag(headers: &mut HeaderMap) {
+    let Some(etag) = headers
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<EntityTag>().ok())
+        .filter(|etag| !etag.weak)
+    else {
+        return;
+    };
+    if let Ok(value) = EntityTag::new_weak(etag.tag().to_string()).try_into_value() {
+        headers.insert(header::ETAG, value);
+    }
+}
+
+/// Weaken the `ETag` of the responses the `Compress` middleware encoded, and
+/// of the 304s answering a client holding such a weakened tag
+pub async fn weaken_encoded_etag(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let if_none_match = req.get_header::<IfNoneMatch>();
+    let mut res = next.call(req).await?;
+
+    let encoded = res
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|v| v != "identity");
+    let held_weak = res.status() == StatusCode::NOT_MODIFIED
+        && res
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<EntityTag>().ok())
+            .is_some_and(|etag| match &if_none_match {
+                Some(IfNoneMatch::Items(tags)) => {
+                    tags.iter().any(|tag| tag.weak && tag.weak_eq(&etag))
+                }
+                _ => false,
+            });
+    if encoded || held_weak {
+        weaken_etag(res.headers_mut());
+    }
+    Ok(res)
+}
+
+/// Read the request body, decoding it according to `Content-Encoding`, and
+/// return it along with the size it had on the wire. The decoded body can't
+/// be larger than the JSON limit
+async fn read_decoded_body(
+    req: &HttpRequest,
+    payload: web::Payload,
+) -> Result<(Bytes, usize), JsonPayloadError> {
+    let limit = req
+        .app_data::<web::Data<BodyLimits>>()
+        .map_or(DEFAULT_JSON_LIMIT, |limits| limits.json);
+    let original_size = Rc::new(Cell::new(0));
+    let counter = original_size.clone();
+    let counted = payload.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            counter.set(counter.get() + chunk.len());
+        }
+    });
+
+    let mut decoded = Decompress::from_headers(counted, req.headers());
+    let mut body = BytesMut::new();
+    while let Some(chunk) = decoded.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limit {
+            return Err(JsonPayloadError::Overflow { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok((body.freeze(), original_size.get()))
+}
+
+/// Parse a JSON request body, transparently decoding compressed bodies, and
+/// report the original and decoded sizes
+pub async fn read_json_body(
+    req: &HttpRequest,
+    payload: web::Payload,
+) -> Result<(Value, Value), JsonPayloadError> {
+    let is_json = req
+        .mime_type()
+        .ok()
+        .flatten()
+        .is_some_and(|mime| mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON));
+    if !is_json {
+        return Err(JsonPayloadError::ContentType);
+    }
+
+    let (body, original_size) = read_decoded_body(req, payload).await?;
+    let parsed = serde_json::from_slice(&body).map_err(JsonPayloadError::Deserialize)?;
+    let content_encoding = req
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok());
+
+    Ok((
+        parsed,
+        json!({
+            "content_encoding": content_encoding,
+            "original_size": original_size,
+            "decoded_size": body.len(),
+        }),
+    ))
+}
+
+fn request_headers(req: &HttpRequest) -> Map<String, Value> {
+    let mut headers = Map::new();
+    for name in req.headers().keys() {
+        let values: Vec<&str> = req
+            .headers()
+            .get_all(name)
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        headers.insert(name.to_string(), json!(values.join(", ")));
+    }
+    headers
+}
+
+/// Answer with a description of the request, always encoded with `encoding`
+/// whatever the client accepts
+async fn encoded_response(
+    req: &HttpRequest,
+    json: bool,
+    encoding: Encoding,
+) -> Result<HttpResponse, Error> {
+    let response = prepare_response(
+        req,
+        json,
+        Some(encoding.as_str()),
+        Some(json!({
+            "encoding": encoding.as_str(),
+            "method": req.method().as_str(),
+            "headers": request_headers(req),
+        })),
+    )
+    .await;
+
+    let (response, body) = response.into_parts();
+    let body = body::to_bytes(body).await?;
+    let encoded = match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&body)?;
+            encoder.finish()?
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&body)?;
+            encoder.finish()?
+        }
+        Encoding::Br => {
+            let mut encoder = CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            encoder.write_all(&body)?;
+            encoder.into_inner()
+        }
+        Encoding::Zstd => zstd::encode_all(body.as_ref(), 0)?,
+    };
+    debug!(
+        "Encoded {} bytes into {} bytes of {}",
+        body.len(),
+        encoded.len(),
+        encoding.as_str()
+    );
+
+    let mut response = response.set_body(encoded);
+    response.headers_mut().insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    weaken_etag(response.headers_mut());
+    Ok(response.map_into_boxed_body())
+}
+
+#[get("/gzip")]
+pub async fn gzip_body(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    encoded_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Encoding::Gzip,
+    )
+    .await
+}
+
+#[get("/deflate")]
+pub async fn deflate_body(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    encoded_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Encoding::Deflate,
+    )
+    .await
+}
+
+#[get("/brotli")]
+pub async fn brotli_body(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    encoded_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Encoding::Br,
+    )
+    .await
+}
+
+#[get("/zstd")]
+pub async fn zstd_body(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    encoded_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Encoding::Zstd,
+    )
+    .await
+}
@@ -0,0 +1,15 @@
+use actix_web::{web, HttpResponse};
+use serde_json::Value;
+
+/// The configuration the service runs with, as reported at `/_config`
+pub struct EffectiveConfig(pub Value);
+
+async fn effective_config(config: web::Data<EffectiveConfig>) -> HttpResponse {
+    HttpResponse::Ok().json(&config.0)
+}
+
+/// Register the `/_config` route reporting `config`
+pub fn configure(cfg: &mut web::ServiceConfig, config: web::Data<EffectiveConfig>) {
+    cfg.app_data(config)
+        .route("/_config", web::get().to(effective_config));
+}
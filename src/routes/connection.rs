@@ -0,0 +1,91 @@
+use crate::add_connection_detail;
+use crate::server::h2c::{H2cConnection, StreamId};
+use crate::server::listener::ListenerInfo;
+use actix_http::ConnectionType;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// IDs given to connections, in the order they are accepted
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// What is known of a connection, kept for all the requests it carries
+pub struct Connection {
+    pub id: u64,
+    pub local_addr: Option<SocketAddr>,
+    pub listener: Arc<ListenerInfo>,
+    pub accepted: Instant,
+    pub requests: Cell<u64>,
+}
+
+impl Connection {
+    pub fn new(local_addr: Option<SocketAddr>, listener: Arc<ListenerInfo>) -> Self {
+        Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            local_addr,
+            listener,
+            accepted: Instant::now(),
+            requests: Cell::new(0),
+        }
+    }
+
+    fn report(&self, report: &mut Value) {
+        let requests = self.requests.get() + 1;
+        self.requests.set(requests);
+        report["id"] = json!(self.id);
+        report["requests"] = json!(requests);
+        report["reused"] = json!(requests > 1);
+        report["age_ms"] = json!(self.accepted.elapsed().as_secs_f64() * 1000.0);
+        report["local_addr"] = json!(self.local_addr.map(|addr| addr.to_string()));
+        report["listener"] = self.listener.report();
+    }
+}
+
+/// Record a new connection in its extensions, whatever the listener
+pub fn register(ext: &mut Extensions, local_addr: Option<SocketAddr>, listener: Arc<ListenerInfo>) {
+    ext.insert(Connection::new(local_addr, listener));
+}
+
+/// Count the requests of the connection and add the connection and protocol
+/// to every response rendered by `prepare_response`
+pub async fn connection(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let head = req.head();
+    let stream = req.extensions().get::<StreamId>().map(|s| (s.id, s.exact));
+    let mut report = json!({
+        "http_version": format!("{:?}", req.version()),
+        "keep_alive": head.connection_type() == ConnectionType::KeepAlive,
+        "id": null,
+        "requests": null,
+        "reused": null,
+        "age_ms": null,
+        "h2c": null,
+        "stream_id": stream.map(|(id, _)| id),
+        "stream_id_exact": stream.map(|(_, exact)| exact),
+        "local_addr": req.app_config().local_addr().to_string(),
+        "listener": null,
+    });
+    // QUIC connections aren't seen by actix, so HTTP/3 requests carry theirs
+    let quic = req.extensions().get::<Rc<Connection>>().cloned();
+    if let Some(conn) = req.request().conn_data::<Connection>() {
+        conn.report(&mut report);
+    } else if let Some(conn) = quic {
+        conn.report(&mut report);
+    }
+    if let Some(h2c) = req.request().conn_data::<H2cConnection>() {
+        report["h2c"] = json!(h2c.negotiation);
+    }
+    add_connection_detail(req.request(), "connection", report);
+
+    next.call(req).await
+}
@@ -0,0 +1,211 @@
+use crate::{add_detail, get_hostname, prepare_response, wants_json, AppState, RequestInfo};
+use actix_web::body::MessageBody;
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{get, web, Error, HttpRequest, Responder};
+use log::debug;
+use serde_json::{json, Map, Value};
+
+/// Query parameters of `/cookies/set` and `/cookies/delete` that are cookie
+/// attributes rather than cookie names
+const ATTRIBUTES: [&str; 8] = [
+    "json", "secure", "httponly", "samesite", "domain", "path", "max_age", "max-age",
+];
+
+/// Name of the cookie used to track which instance served the first response
+pub struct StickyCookie(pub String);
+
+fn request_cookies(req: &HttpRequest) -> Map<String, Value> {
+    req.cookies()
+        .map(|jar| {
+            jar.iter()
+                .map(|c| (c.name().to_string(), json!(c.value())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Valueless flags like `?secure` count as set, unless explicitly disabled
+fn flag(params: &[(String, String)], name: &str) -> bool {
+    param(params, name).is_some_and(|v| !matches!(v, "false" | "0" | "no"))
+}
+
+fn cookie_names(params: &[(String, String)]) -> impl Iterator<Item = &(String, String)> {
+    params
+        .iter()
+        .filter(|(k, _)| !ATTRIBUTES.contains(&k.to_ascii_lowercase().as_str()))
+}
+
+#[get("/cookies")]
+pub async fn get_cookies(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let cookies = request_cookies(&req);
+
+    prepare_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Some("Cookies"),
+        Some(json!({ "cookies": cookies })),
+    )
+    .await
+}
+
+/// Set every non attribute query parameter as a cookie, e.g.
+/// `/cookies/set?theme=dark&lang=en&secure&samesite=strict&max_age=60`
+#[get("/cookies/set")]
+pub async fn set_cookies(
+    req: HttpRequest,
+    params: web::Query<Vec<(String, String)>>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    let same_site = match param(&params, "samesite").map(str::to_ascii_lowercase) {
+        Some(v) if v == "strict" => Some(SameSite::Strict),
+        Some(v) if v == "lax" => Some(SameSite::Lax),
+        Some(v) if v == "none" => Some(SameSite::None),
+        Some(v) => {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "Invalid SameSite value {}, expected strict, lax or none",
+                v
+            )))
+        }
+        None => None,
+    };
+    let max_age = match param(&params, "max_age").or(param(&params, "max-age")) {
+        Some(v) => Some(v.parse::<i64>().map_err(|_| {
+            actix_web::error::ErrorBadRequest(format!("Invalid Max-Age value {}", v))
+        })?),
+        None => None,
+    };
+
+    let mut set = Vec::new();
+    for (name, value) in cookie_names(&params) {
+        let mut cookie = Cookie::new(name.clone(), value.clone());
+        cookie.set_path(param(&params, "path").unwrap_or("/").to_string());
+        if let Some(domain) = param(&params, "domain") {
+            cookie.set_domain(domain.to_string());
+        }
+        if flag(&params, "secure") {
+            cookie.set_secure(true);
+        }
+        if flag(&params, "httponly") {
+            cookie.set_http_only(true);
+        }
+        if let Some(same_site) = same_site {
+            cookie.set_same_site(same_site);
+        }
+        if let Some(max_age) = max_age {
+            cookie.set_max_age(Duration::seconds(max_age));
+        }
+        debug!("Setting cookie {}", cookie);
+        set.push(cookie);
+    }
+
+    let mut response = prepare_response(
+        &req,
+        wants_json(&req, param(&params, "json").is_some(), &data),
+        Some("Cookies set"),
+        Some(json!({
+            "set": set.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            "cookies": request_cookies(&req),
+        })),
+    )
+    .await;
+    for cookie in &set {
+        response.add_cookie(cookie)?;
+    }
+
+    Ok(response)
+}
+
+/// Expire the named cookies, or every cookie sent with the request when no
+/// name is given, e.g. `/cookies/delete?theme&lang`
+#[get("/cookies/delete")]
+pub async fn delete_cookies(
+    req: HttpRequest,
+    params: web::Query<Vec<(String, String)>>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    let mut names: Vec<String> = cookie_names(&params).map(|(k, _)| k.clone()).collect();
+    if names.is_empty() {
+        names = request_cookies(&req).keys().cloned().collect();
+    }
+
+    let mut response = prepare_response(
+        &req,
+        wants_json(&req, param(&params, "json").is_some(), &data),
+        Some("Cookies deleted"),
+        Some(json!({ "deleted": names })),
+    )
+    .await;
+    for name in names {
+        let mut cookie = Cookie::new(name, "");
+        cookie.set_path(param(&params, "path").unwrap_or("/").to_string());
+        if let Some(domain) = param(&params, "domain") {
+            cookie.set_domain(domain.to_string());
+        }
+        response.add_removal_cookie(&cookie)?;
+    }
+
+    Ok(response)
+}
+
+/// Report whether the request came back to the instance that served the
+/// first response, based on a cookie set on that first response
+pub async fn sticky_session(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(cookie_name) = req
+        .app_data::<web::Data<StickyCookie>>()
+        .map(|c| c.0.clone())
+    else {
+        return next.call(req).await;
+    };
+
+    let instance = get_hostname().await;
+    let previous = req.cookie(&cookie_name).map(|c| c.value().to_string());
+    let status = match &previous {
+        None => "new",
+        Some(previous) if *previous == instance => "same-instance",
+        Some(_) => "different-instance",
+    };
+    debug!("Sticky session check: {}", status);
+
+    add_detail(
+        req.request(),
+        "sticky",
+        json!({
+            "cookie": cookie_name,
+            "instance": instance,
+            "first_instance": previous,
+            "status": status,
+        }),
+    );
+
+    let mut res = next.call(req).await?;
+    res.headers_mut().insert(
+        HeaderName::from_static("x-sticky-session"),
+        HeaderValue::from_static(status),
+    );
+    if previous.is_none() {
+        let mut cookie = Cookie::new(cookie_name, instance);
+        cookie.set_path("/");
+        cookie.set_http_only(true);
+        res.response_mut().add_cookie(&cookie)?;
+    }
+
+    Ok(res)
+}
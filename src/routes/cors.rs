@@ -0,0 +1,312 @@
+use crate::utils::structs::{Result, WesterError};
+use crate::{prepare_response, wants_json, AppState, RequestInfo};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse, Responder};
+use log::{debug, info};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+/// An allowed origin: `*`, an exact origin, a wildcard like
+/// `https://*.example.com` or a regex prefixed with `~`
+#[derive(Debug)]
+enum OriginPattern {
+    Any,
+    Exact(String),
+    Regex(Regex),
+}
+
+impl OriginPattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        if pattern == "*" {
+            Ok(OriginPattern::Any)
+        } else if let Some(regex) = pattern.strip_prefix('~') {
+            Ok(OriginPattern::Regex(Regex::new(regex)?))
+        } else if pattern.contains('*') {
+            let regex = pattern
+                .split('*')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join("[^/]*");
+            Ok(OriginPattern::Regex(Regex::new(&format!("^{}$", regex))?))
+        } else {
+            Ok(OriginPattern::Exact(
+                pattern.trim_end_matches('/').to_string(),
+            ))
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginPattern::Any => true,
+            OriginPattern::Exact(exact) => exact.eq_ignore_ascii_case(origin),
+            OriginPattern::Regex(regex) => regex.is_match(origin),
+        }
+    }
+}
+
+/// CORS policy applied by the `cors` middleware
+pub struct CorsConfig {
+    origins: Vec<(String, OriginPattern)>,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u32>,
+}
+
+impl CorsConfig {
+    /// An empty `headers` list allows whatever headers the preflight asks for
+    pub fn new(
+        origins: Vec<String>,
+        methods: Vec<String>,
+        headers: Vec<String>,
+        credentials: bool,
+        max_age: Option<u32>,
+    ) -> Result<Self> {
+        let origins = origins
+            .into_iter()
+            .map(|o| OriginPattern::parse(&o).map(|pattern| (o, pattern)))
+            .collect::<Result<_>>()?;
+        let methods = methods
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| WesterError::Other(format!("Invalid CORS method {}", m)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            origins,
+            methods,
+            headers: headers.iter().map(|h| h.to_ascii_lowercase()).collect(),
+            credentials,
+            max_age,
+        })
+    }
+
+    fn report(&self) -> Value {
+        json!({
+            "origins": self.origins.iter().map(|(o, _)| o).collect::<Vec<_>>(),
+            "methods": self.methods.iter().map(Method::as_str).collect::<Vec<_>>(),
+            "headers": if self.headers.is_empty() { json!("*") } else { json!(self.headers) },
+            "credentials": self.credentials,
+            "max_age": self.max_age,
+        })
+    }
+
+    /// Decide what CORS headers a request gets
+    fn decide(&self, method: &Method, headers: &HeaderMap) -> CorsDecision {
+        let origin = header_str(headers, header::ORIGIN);
+        let request_method = header_str(headers, header::ACCESS_CONTROL_REQUEST_METHOD);
+        let request_headers = header_str(headers, header::ACCESS_CONTROL_REQUEST_HEADERS);
+        let preflight = method == Method::OPTIONS && request_method.is_some();
+        let mut decision = CorsDecision {
+            preflight,
+            allowed: false,
+            reason: String::new(),
+            headers: Vec::new(),
+        };
+
+        let Some(origin) = origin else {
+            decision.reason = "No Origin header, not a CORS request".to_string();
+            return decision;
+        };
+        let Some((pattern, _)) = self.origins.iter().find(|(_, p)| p.matches(origin)) else {
+            decision.reason = format!("Origin {} is not allowed", origin);
+            return decision;
+        };
+
+        if preflight {
+            let request_method = request_method.unwrap_or_default();
+            if !self.methods.iter().any(|m| m.as_str() == request_method) {
+                decision.reason = format!("Method {} is not allowed", request_method);
+                return decision;
+            }
+            let requested: Vec<String> = request_headers
+                .unwrap_or_default()
+                .split(',')
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect();
+            if !self.headers.is_empty() {
+                if let Some(denied) = requested.iter().find(|h| !self.headers.contains(h)) {
+                    decision.reason = format!("Header {} is not allowed", denied);
+                    return decision;
+                }
+            }
+
+            let methods: Vec<&str> = self.methods.iter().map(Method::as_str).collect();
+            decision.push(header::ACCESS_CONTROL_ALLOW_METHODS, methods.join(", "));
+            let allowed_headers = if self.headers.is_empty() {
+                requested
+            } else {
+                self.headers.clone()
+            };
+            if !allowed_headers.is_empty() {
+                decision.push(
+                    header::ACCESS_CONTROL_ALLOW_HEADERS,
+                    allowed_headers.join(", "),
+                );
+            }
+            if let Some(max_age) = self.max_age {
+                decision.push(header::ACCESS_CONTROL_MAX_AGE, max_age.to_string());
+            }
+        }
+
+        // Credentials can't be used with a literal `*`, so the origin is echoed
+        if pattern == "*" && !self.credentials {
+            decision.push(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*".to_string());
+        } else {
+            decision.push(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.to_string());
+            decision.push(header::VARY, "Origin".to_string());
+        }
+        if self.credentials {
+            decision.push(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true".to_string());
+        }
+        decision.allowed = true;
+        decision.reason = format!("Origin {} matches {}", origin, pattern);
+        decision
+    }
+}
+
+/// The outcome of the CORS policy for a request, kept in its extensions
+#[derive(Clone)]
+struct CorsDecision {
+    preflight: bool,
+    allowed: bool,
+    reason: String,
+    headers: Vec<(HeaderName, String)>,
+}
+
+impl CorsDecision {
+    fn push(&mut self, name: HeaderName, value: String) {
+        self.headers.push((name, value));
+    }
+
+    fn report(&self) -> Value {
+        let headers: Map<String, Value> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect();
+        json!({
+            "allowed": self.allowed,
+            "reason": self.reason,
+            "headers": headers,
+        })
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Apply the CORS policy, answering preflights directly except on `/cors`
+/// which reports them
+pub async fn cors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(config) = req.app_data::<web::Data<CorsConfig>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    if !req.headers().contains_key(header::ORIGIN) {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let decision = config.decide(req.method(), req.headers());
+    if decision.allowed {
+        debug!(
+            "CORS request {} {}: {}",
+            req.method(),
+            req.path(),
+            decision.reason
+        );
+    } else {
+        info!(
+            "CORS request {} {} denied: {}",
+            req.method(),
+            req.path(),
+            decision.reason
+        );
+    }
+    req.extensions_mut().insert(decision.clone());
+
+    let mut res = if decision.preflight && req.path() != "/cors" {
+        let response = if decision.allowed {
+            HttpResponse::NoContent().finish()
+        } else {
+            HttpResponse::Forbidden().body(decision.reason.clone())
+        };
+        req.into_response(response).map_into_right_body()
+    } else {
+        next.call(req).await?.map_into_left_body()
+    };
+
+    for (name, value) in decision.headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            if name == header::VARY {
+                res.headers_mut().append(name, value);
+            } else {
+                res.headers_mut().insert(name, value);
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Report the CORS request the browser sent and the policy decision, for any
+/// method so preflights can be inspected too
+async fn cors_report(
+    req: HttpRequest,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let headers = req.headers();
+    let request = json!({
+        "method": req.method().as_str(),
+        "origin": header_str(headers, header::ORIGIN),
+        "preflight": req.method() == Method::OPTIONS
+            && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD),
+        "access_control_request_method":
+            header_str(headers, header::ACCESS_CONTROL_REQUEST_METHOD),
+        "access_control_request_headers":
+            header_str(headers, header::ACCESS_CONTROL_REQUEST_HEADERS),
+    });
+
+    let config = req.app_data::<web::Data<CorsConfig>>();
+    let decision = match (config, req.extensions().get::<CorsDecision>()) {
+        (None, _) => json!({ "allowed": false, "reason": "CORS is not enabled" }),
+        (Some(_), Some(decision)) => decision.report(),
+        (Some(_), None) => json!({
+            "allowed": false,
+            "reason": "No Origin header, not a CORS request",
+        }),
+    };
+
+    prepare_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Some("CORS"),
+        Some(json!({
+            "request": request,
+            "policy": config.map(|c| c.report()),
+            "decision": decision,
+        })),
+    )
+    .await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/cors", web::to(cors_report));
+}
@@ -0,0 +1,170 @@
+use actix_web::body::{BodySize, BodyStream, BoxBody, MessageBody, SizedStream};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::header::{self, ContentType};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{get, web, Error, HttpResponse};
+use futures_util::stream::{self, Stream, StreamExt};
+use log::debug;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Size of the chunks `/download/{size}` is streamed in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bandwidth limit applied to every response, in bytes per second
+pub struct MaxBandwidth(pub u64);
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+    rate: Option<String>,
+}
+
+/// Parse sizes like `512`, `10KB`, `1.5MiB` or `1GiB`, decimal and binary
+/// units being both accepted
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size {}", size))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "kib" => 1 << 10,
+        "m" | "mb" => 1_000_000,
+        "mib" => 1 << 20,
+        "g" | "gb" => 1_000_000_000,
+        "gib" => 1 << 30,
+        "t" | "tb" => 1_000_000_000_000,
+        "tib" => 1 << 40,
+        _ => return Err(format!("Invalid size unit {} in {}", unit, size)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parse a bandwidth in bytes per second, with the units of `parse_size`
+pub fn parse_rate(rate: &str) -> Result<u64, String> {
+    match parse_size(rate)? {
+        0 => Err("Rate must be greater than 0".to_string()),
+        rate => Ok(rate),
+    }
+}
+
+/// Delay the chunks of `stream` so that at most `rate` bytes are sent per
+/// second, splitting them to keep the flow smooth
+pub fn throttle<S, E>(stream: S, rate: u64) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let piece_size = (rate / 10).max(1) as usize;
+    let started = Instant::now();
+
+    stream::unfold(
+        (stream, Bytes::new(), 0u64),
+        move |(mut stream, mut pending, mut sent)| async move {
+            if pending.is_empty() {
+                match stream.next().await? {
+                    Ok(chunk) => pending = chunk,
+                    Err(err) => return Some((Err(err), (stream, pending, sent))),
+                }
+            }
+
+            let piece = pending.split_to(piece_size.min(pending.len()));
+            sent += piece.len() as u64;
+            let due = started + Duration::from_secs_f64(sent as f64 / rate as f64);
+            tokio::time::sleep_until(due.into()).await;
+
+            Some((Ok(piece), (stream, pending, sent)))
+        },
+    )
+}
+
+/// `size` deterministic bytes, the alphabet repeated, in chunks sharing the
+/// same buffer
+fn download_stream(size: u64) -> impl Stream<Item = Result<Bytes, Error>> {
+    let pattern: Bytes = (0..CHUNK_SIZE + 26)
+        .map(|i| b'a' + (i % 26) as u8)
+        .collect::<Vec<_>>()
+        .into();
+
+    stream::unfold(0u64, move |offset| {
+        let pattern = pattern.clone();
+        async move {
+            if offset >= size {
+                return None;
+            }
+            let len = (size - offset).min(CHUNK_SIZE as u64) as usize;
+            let start = (offset % 26) as usize;
+            Some((Ok(pattern.slice(start..start + len)), offset + len as u64))
+        }
+    })
+}
+
+/// Stream `size` bytes of deterministic data, e.g. `/download/1GiB`,
+/// throttled to `?rate=` bytes per second when given
+#[get("/download/{size}")]
+pub async fn download(
+    size: web::Path<String>,
+    query: web::Query<DownloadQuery>,
+) -> Result<HttpResponse, Error> {
+    let size = parse_size(&size).map_err(ErrorBadRequest)?;
+    let rate = query
+        .rate
+        .as_deref()
+        .map(parse_rate)
+        .transpose()
+        .map_err(ErrorBadRequest)?;
+    debug!("Streaming {} bytes at {:?} bytes/s", size, rate);
+
+    let body = match rate {
+        Some(rate) => BoxBody::new(SizedStream::new(
+            size,
+            throttle(Box::pin(download_stream(size)), rate),
+        )),
+        None => BoxBody::new(SizedStream::new(size, download_stream(size))),
+    };
+
+    // The data is meant to travel as is, so it is never compressed
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentType::octet_stream())
+        .insert_header(header::ContentEncoding::Identity)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"download-{}.bin\"", size),
+        ))
+        .body(body))
+}
+
+/// Throttle every response body to the `--max-bandwidth` limit
+pub async fn limit_bandwidth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(rate) = req.app_data::<web::Data<MaxBandwidth>>().map(|m| m.0) else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+
+    let res = next.call(req).await?;
+    Ok(res.map_body(|_, body| {
+        let size = body.size();
+        let mut body = body.boxed();
+        if matches!(size, BodySize::None | BodySize::Sized(0)) {
+            return body;
+        }
+        let chunks = stream::poll_fn(move |cx| body.as_pin_mut().poll_next(cx));
+        let throttled = throttle(Box::pin(chunks), rate);
+        match size {
+            BodySize::Sized(size) => BoxBody::new(SizedStream::new(size, throttled)),
+            _ => BoxBody::new(BodyStream::new(throttled)),
+        }
+    }))
+}
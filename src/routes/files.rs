@@ -0,0 +1,166 @@
+use crate::utils::structs::{Result, WesterError};
+use crate::{wants_json, AppState, RequestInfo};
+use actix_files::{Directory, Files};
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use log::debug;
+use maud::{html, DOCTYPE};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde_json::{json, Value};
+use std::io;
+use std::path::PathBuf;
+
+/// Characters escaped in the links of the directory index
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Directory served with `--serve-dir`, mounted under `prefix`
+#[derive(Clone)]
+pub struct ServeDir {
+    pub dir: PathBuf,
+    pub prefix: String,
+}
+
+impl ServeDir {
+    pub fn new(dir: PathBuf, prefix: &str) -> Result<Self> {
+        if !dir.is_dir() {
+            return Err(WesterError::Other(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+
+        Ok(Self {
+            dir,
+            prefix: format!("/{}", prefix.trim_matches('/')),
+        })
+    }
+}
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// Visible entries of the directory, directories first then by name
+fn read_entries(dir: &Directory) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in dir.path.read_dir()? {
+        if !dir.is_visible(&entry) {
+            continue;
+        }
+        let entry = entry?;
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        entries.push(Entry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        });
+    }
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(entries)
+}
+
+fn href(base: &str, entry: &Entry) -> String {
+    let name = utf8_percent_encode(&entry.name, PATH_SEGMENT);
+    if entry.is_dir {
+        format!("{}/{}/", base.trim_end_matches('/'), name)
+    } else {
+        format!("{}/{}", base.trim_end_matches('/'), name)
+    }
+}
+
+/// Directory index, as a page or as JSON when negotiated with `Accept` or
+/// `?json` like the other routes
+fn render_listing(dir: &Directory, req: &HttpRequest) -> io::Result<ServiceResponse> {
+    let entries = read_entries(dir)?;
+    let json = web::Query::<RequestInfo>::from_query(req.query_string())
+        .is_ok_and(|info| info.json.is_some());
+    let allow_json = req
+        .app_data::<web::Data<AppState>>()
+        .is_some_and(|data| wants_json(req, json, data));
+    let base = req.path();
+    debug!(
+        "Listing {} entries of {}",
+        entries.len(),
+        dir.path.display()
+    );
+
+    let response = if allow_json {
+        let listing: Vec<Value> = entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "name": entry.name,
+                    "type": if entry.is_dir { "directory" } else { "file" },
+                    "size": entry.size,
+                    "modified": entry.modified.map(|m| m.to_rfc3339()),
+                    "href": href(base, entry),
+                })
+            })
+            .collect();
+        HttpResponse::Ok().json(json!({ "path": base, "entries": listing }))
+    } else {
+        let page = html! {
+            (DOCTYPE)
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title { "Index of " (base) }
+            }
+            body {
+                h1 { "Index of " (base) }
+                table {
+                    tr { th { "Name" } th { "Size" } th { "Modified" } }
+                    @if dir.path != dir.base {
+                        tr { td { a href=".." { "../" } } td {} td {} }
+                    }
+                    @for entry in &entries {
+                        tr {
+                            td {
+                                a href=(href(base, entry)) {
+                                    (entry.name) @if entry.is_dir { "/" }
+                                }
+                            }
+                            td { @if !entry.is_dir { (entry.size) } }
+                            td { (entry.modified.map(|m| m.to_rfc3339()).unwrap_or_default()) }
+                        }
+                    }
+                }
+            }
+        };
+        HttpResponse::Ok()
+            .append_header(header::ContentType::html())
+            .body(page.into_string())
+    };
+
+    Ok(ServiceResponse::new(req.clone(), response))
+}
+
+/// Serve the directory with MIME types, ranges and conditional requests
+/// handled by `actix-files`, and a directory index
+pub fn configure(cfg: &mut web::ServiceConfig, serve_dir: &ServeDir) {
+    cfg.service(
+        Files::new(&serve_dir.prefix, &serve_dir.dir)
+            .show_files_listing()
+            .redirect_to_slash_directory()
+            .files_listing_renderer(render_listing),
+    );
+}
@@ -0,0 +1,274 @@
+use crate::utils::structs::Result;
+use crate::{prepare_response, wants_json, AppState};
+use actix_web::{get, web, HttpRequest, Responder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, AlgorithmFamily, DecodingKey, Validation};
+use log::debug;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+/// Where to look for tokens on `/jwt` and which keys to validate them with
+#[derive(Default)]
+pub struct JwtConfig {
+    pub header: Option<String>,
+    pub cookie: Option<String>,
+    pub hmac_secret: Option<Vec<u8>>,
+    pub jwks: Option<JwkSet>,
+}
+
+impl JwtConfig {
+    pub fn new(
+        header: Option<String>,
+        cookie: Option<String>,
+        hmac_secret: Option<String>,
+        jwks_file: Option<&Path>,
+    ) -> Result<Self> {
+        let jwks = match jwks_file {
+            Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+            None => None,
+        };
+
+        Ok(Self {
+            header,
+            cookie,
+            hmac_secret: hmac_secret.map(String::into_bytes),
+            jwks,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JwtQuery {
+    json: Option<String>,
+    /// Read the token from this header instead of the configured one
+    header: Option<String>,
+    /// Read the token from this cookie instead of the configured one
+    cookie: Option<String>,
+}
+
+/// Strip an optional `Bearer ` scheme from a header value
+fn strip_bearer(value: &str) -> &str {
+    match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+        _ => value,
+    }
+}
+
+fn token_from_header(req: &HttpRequest, name: &str) -> Option<(String, String)> {
+    let value = req.headers().get(name)?.to_str().ok()?.trim();
+    let token = strip_bearer(value);
+    (!token.is_empty()).then(|| {
+        (
+            format!("header:{}", name.to_ascii_lowercase()),
+            token.to_string(),
+        )
+    })
+}
+
+fn token_from_cookie(req: &HttpRequest, name: &str) -> Option<(String, String)> {
+    req.cookie(name)
+        .filter(|c| !c.value().is_empty())
+        .map(|c| (format!("cookie:{}", name), c.value().to_string()))
+}
+
+/// Find the token and where it came from. A header or cookie named in the
+/// query wins over the configured ones, which win over `Authorization`.
+fn find_token(req: &HttpRequest, query: &JwtQuery, config: &JwtConfig) -> Option<(String, String)> {
+    if let Some(name) = query.header.as_deref() {
+        return token_from_header(req, name);
+    }
+    if let Some(name) = query.cookie.as_deref() {
+        return token_from_cookie(req, name);
+    }
+
+    config
+        .header
+        .as_deref()
+        .and_then(|name| token_from_header(req, name))
+        .or_else(|| {
+            config
+                .cookie
+                .as_deref()
+                .and_then(|name| token_from_cookie(req, name))
+        })
+        .or_else(|| token_from_header(req, "authorization"))
+}
+
+fn decode_segment(segment: &str) -> std::result::Result<Value, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment.trim_end_matches('='))
+        .map_err(|e| format!("invalid base64url: {}", e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("invalid JSON: {}", e))
+}
+
+/// Human readable distance from now, e.g. "in 5m 3s" or "2h 10m ago"
+fn describe_offset(seconds: i64) -> String {
+    let abs = seconds.unsigned_abs();
+    let text = match abs {
+        0..60 => format!("{}s", abs),
+        60..3600 => format!("{}m {}s", abs / 60, abs % 60),
+        3600..86400 => format!("{}h {}m", abs / 3600, (abs % 3600) / 60),
+        _ => format!("{}d {}h", abs / 86400, (abs % 86400) / 3600),
+    };
+
+    if seconds >= 0 {
+        format!("in {}", text)
+    } else {
+        format!("{} ago", text)
+    }
+}
+
+fn describe_timestamp(timestamp: i64, now: DateTime<Utc>) -> Value {
+    let offset = timestamp - now.timestamp();
+    json!({
+        "timestamp": timestamp,
+        "date": DateTime::from_timestamp(timestamp, 0).map(|d| d.to_rfc3339()),
+        "seconds_from_now": offset,
+        "relative": describe_offset(offset),
+    })
+}
+
+/// Check the signature against the configured HMAC secret or JWKS keys.
+/// Time based claims are reported separately, so only the signature is
+/// validated here.
+fn verify_signature(token: &str, config: &JwtConfig) -> Value {
+    let header = match decode_header(token) {
+        Ok(header) => header,
+        Err(e) => return json!({ "checked": false, "error": e.to_string() }),
+    };
+
+    let keys: Vec<(String, DecodingKey)> = match header.alg.family() {
+        AlgorithmFamily::Hmac => config
+            .hmac_secret
+            .iter()
+            .map(|secret| ("hmac secret".to_string(), DecodingKey::from_secret(secret)))
+            .collect(),
+        _ => config
+            .jwks
+            .iter()
+            .flat_map(|set| set.keys.iter())
+            .filter(|jwk| header.kid.is_none() || jwk.common.key_id == header.kid)
+            .filter_map(|jwk| {
+                let name = format!(
+                    "jwks kid {}",
+                    jwk.common.key_id.as_deref().unwrap_or("<none>")
+                );
+                DecodingKey::from_jwk(jwk).ok().map(|key| (name, key))
+            })
+            .collect(),
+    };
+
+    if keys.is_empty() {
+        return json!({
+            "checked": false,
+            "algorithm": header.alg,
+            "error": "No key configured for this algorithm",
+        });
+    }
+
+    let mut validation = Validation::new(header.alg);
+    validation.validate_exp = false;
+    validation.validate_nbf = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    let mut last_error = None;
+    for (name, key) in &keys {
+        match decode::<Value>(token, key, &validation) {
+            Ok(_) => {
+                return json!({
+                    "checked": true,
+                    "valid": true,
+                    "algorithm": header.alg,
+                    "key": name,
+                })
+            }
+            Err(e) => last_error = Some(e.to_string()),
+        }
+    }
+
+    json!({
+        "checked": true,
+        "valid": false,
+        "algorithm": header.alg,
+        "error": last_error,
+    })
+}
+
+/// Decode a token without trusting it and describe header, claims, time
+/// validity and, when keys are configured, the signature
+pub fn inspect_token(token: &str, config: &JwtConfig, now: DateTime<Utc>) -> Map<String, Value> {
+    let mut report = Map::new();
+    report.insert("server_time".to_string(), json!(now.to_rfc3339()));
+
+    let segments: Vec<&str> = token.split('.').collect();
+    if segments.len() != 3 {
+        report.insert(
+            "error".to_string(),
+            json!(format!(
+                "Expected 3 dot separated segments, found {}",
+                segments.len()
+            )),
+        );
+        return report;
+    }
+
+    for (name, segment) in [("header", segments[0]), ("claims", segments[1])] {
+        let value = decode_segment(segment).unwrap_or_else(|e| json!({ "error": e }));
+        report.insert(name.to_string(), value);
+    }
+
+    let mut times = Map::new();
+    for claim in ["exp", "nbf", "iat"] {
+        if let Some(timestamp) = report["claims"].get(claim).and_then(Value::as_i64) {
+            times.insert(claim.to_string(), describe_timestamp(timestamp, now));
+        }
+    }
+    let offset = |claim: &str| {
+        times
+            .get(claim)
+            .and_then(|t| t["seconds_from_now"].as_i64())
+    };
+    report.insert(
+        "expired".to_string(),
+        json!(offset("exp").is_some_and(|o| o <= 0)),
+    );
+    report.insert(
+        "not_yet_valid".to_string(),
+        json!(offset("nbf").is_some_and(|o| o > 0)),
+    );
+    report.insert("times".to_string(), Value::Object(times));
+
+    report.insert("signature".to_string(), verify_signature(token, config));
+    report
+}
+
+#[get("/jwt")]
+pub async fn jwt_inspect(
+    req: HttpRequest,
+    query: web::Query<JwtQuery>,
+    config: web::Data<JwtConfig>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let report = match find_token(&req, &query, &config) {
+        Some((source, token)) => {
+            debug!("Inspecting JWT from {}", source);
+            let mut report = inspect_token(&token, &config, Utc::now());
+            report.insert("source".to_string(), json!(source));
+            Value::Object(report)
+        }
+        None => json!({ "error": "No token found" }),
+    };
+
+    prepare_response(
+        &req,
+        wants_json(&req, query.json.is_some(), &data),
+        Some("JWT inspection"),
+        Some(report),
+    )
+    .await
+}
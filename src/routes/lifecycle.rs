@@ -0,0 +1,110 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{web, Error, HttpResponse};
+use serde_json::json;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+/// The requests being served by all the workers, and whether the service is
+/// shutting down
+#[derive(Default)]
+pub struct Lifecycle {
+    in_flight: AtomicUsize,
+    draining: AtomicBool,
+}
+
+impl Lifecycle {
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Fail readiness from now on, requests still being served
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A request counted in flight until dropped
+struct InFlight(web::Data<Lifecycle>);
+
+impl InFlight {
+    fn new(lifecycle: web::Data<Lifecycle>) -> Self {
+        lifecycle.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(lifecycle)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A response body keeping its request in flight until it's sent
+struct InFlightBody {
+    body: BoxBody,
+    _request: InFlight,
+}
+
+impl MessageBody for InFlightBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.get_mut().body).poll_next(cx)
+    }
+}
+
+/// Count every request in flight, from its head until its response body is
+/// sent or dropped
+pub async fn track_in_flight(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(lifecycle) = req.app_data::<web::Data<Lifecycle>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+
+    let request = InFlight::new(lifecycle);
+    let res = next.call(req).await?;
+    Ok(res
+        .map_body(|_, body| InFlightBody {
+            body: body.boxed(),
+            _request: request,
+        })
+        .map_into_boxed_body())
+}
+
+/// Readiness probe, failing once the service is shutting down
+async fn readyz(lifecycle: web::Data<Lifecycle>) -> HttpResponse {
+    let body = json!({
+        "ready": !lifecycle.is_draining(),
+        "in_flight": lifecycle.in_flight(),
+    });
+    match lifecycle.is_draining() {
+        true => HttpResponse::ServiceUnavailable().json(body),
+        false => HttpResponse::Ok().json(body),
+    }
+}
+
+/// Register the readiness probe, sharing `lifecycle` with the server
+pub fn configure(cfg: &mut web::ServiceConfig, lifecycle: web::Data<Lifecycle>) {
+    cfg.app_data(lifecycle)
+        .route("/readyz", web::get().to(readyz));
+}
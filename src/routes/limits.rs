@@ -0,0 +1,82 @@
+use actix_http::BoxedPayloadStream;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::middleware::Next;
+use actix_web::{dev, http::header, web, Error, HttpMessage, HttpResponse};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+
+/// Largest JSON body parsed by default, the default `web::Json` limit
+pub const DEFAULT_JSON_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Sizes of the request bodies the app accepts
+#[derive(Clone, Debug)]
+pub struct BodyLimits {
+    /// Largest JSON body parsed, decoded
+    pub json: usize,
+    /// Largest body of any request, as sent
+    pub payload: Option<usize>,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            json: DEFAULT_JSON_LIMIT,
+            payload: None,
+        }
+    }
+}
+
+impl BodyLimits {
+    pub fn report(&self) -> Value {
+        json!({
+            "json": self.json,
+            "payload": self.payload,
+        })
+    }
+}
+
+/// Answer 413 to requests whose body is larger than the payload limit, at
+/// once when they announce it with `Content-Length`, or by failing the body
+/// once the limit is reached
+pub async fn limit_payload(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(limit) = req
+        .app_data::<web::Data<BodyLimits>>()
+        .and_then(|limits| limits.payload)
+    else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+
+    let length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if length.is_some_and(|length| length > limit) {
+        let res = HttpResponse::PayloadTooLarge().json(json!({
+            "error": format!("Request body larger than {} bytes", limit),
+        }));
+        return Ok(req.into_response(res).map_into_boxed_body());
+    }
+
+    let mut received = 0;
+    let payload = req.take_payload().map(move |chunk| {
+        let chunk = chunk?;
+        received += chunk.len();
+        match received > limit {
+            true => Err(PayloadError::Overflow),
+            false => Ok(chunk),
+        }
+    });
+    req.set_payload(dev::Payload::from(Box::pin(payload) as BoxedPayloadStream));
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_boxed_body)
+}
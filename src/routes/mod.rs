@@ -0,0 +1,19 @@
+pub mod admin;
+pub mod cache;
+pub mod client_ip;
+pub mod compression;
+pub mod config;
+pub mod connection;
+pub mod cookies;
+pub mod cors;
+pub mod download;
+pub mod files;
+pub mod jwt;
+pub mod lifecycle;
+pub mod limits;
+pub mod oidc;
+pub mod proxy_protocol;
+pub mod range;
+pub mod redirect;
+pub mod reload;
+pub mod upload;
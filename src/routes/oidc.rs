@@ -0,0 +1,830 @@
+use crate::routes::admin::constant_time_eq;
+use crate::utils::structs::{Result, WesterError};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::Utc;
+use clap::ValueEnum;
+use jsonwebtoken::jwk::{Jwk, JwkSet, ThumbprintHash};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use log::{debug, info, warn};
+use maud::{html, Markup, DOCTYPE};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::Url;
+
+/// Algorithm used to sign the tokens issued by the mock provider
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SigningAlgorithm {
+    Rs256,
+    Es256,
+}
+
+impl From<SigningAlgorithm> for Algorithm {
+    fn from(alg: SigningAlgorithm) -> Self {
+        match alg {
+            SigningAlgorithm::Rs256 => Algorithm::RS256,
+            SigningAlgorithm::Es256 => Algorithm::ES256,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct OidcClient {
+    pub client_id: String,
+    /// Clients without a secret are public and must use PKCE
+    pub client_secret: Option<String>,
+    /// Allowed redirect URIs, any URI is accepted when empty
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    /// Extra claims added to tokens issued to this client
+    #[serde(default)]
+    pub claims: Map<String, Value>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct OidcUser {
+    pub username: String,
+    pub password: String,
+    /// Claims returned in the id token and from `/userinfo`
+    #[serde(default)]
+    pub claims: Map<String, Value>,
+}
+
+/// Contents of the `--oidc-config` file
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct OidcConfig {
+    /// Fixed issuer, derived from the request when not set
+    pub issuer: Option<String>,
+    /// PEM encoded private key, generated at startup when not set
+    pub signing_key_file: Option<PathBuf>,
+    pub access_token_ttl: i64,
+    pub refresh_token_ttl: i64,
+    pub code_ttl: i64,
+    /// Extra claims added to every issued token
+    pub claims: Map<String, Value>,
+    /// Only the built-in client without a file, none when the file leaves
+    /// them out
+    #[serde(default)]
+    pub clients: Vec<OidcClient>,
+    /// Only the built-in user without a file, none when the file leaves
+    /// them out
+    #[serde(default)]
+    pub users: Vec<OidcUser>,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            signing_key_file: None,
+            access_token_ttl: 3600,
+            refresh_token_ttl: 86400,
+            code_ttl: 60,
+            claims: Map::new(),
+            clients: vec![OidcClient {
+                client_id: "rustwester".to_string(),
+                client_secret: Some("secret".to_string()),
+                redirect_uris: Vec::new(),
+                claims: Map::new(),
+            }],
+            users: vec![OidcUser {
+                username: "user".to_string(),
+                password: "password".to_string(),
+                claims: Map::from_iter([
+                    ("name".to_string(), json!("Test User")),
+                    ("email".to_string(), json!("user@example.com")),
+                    ("email_verified".to_string(), json!(true)),
+                ]),
+            }],
+        }
+    }
+}
+
+impl OidcConfig {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+struct AuthorizationCode {
+    client_id: String,
+    redirect_uri: String,
+    username: String,
+    scope: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    expires_at: i64,
+}
+
+struct RefreshGrant {
+    client_id: String,
+    username: Option<String>,
+    scope: String,
+    expires_at: i64,
+}
+
+/// Signing keys, registered clients and users, and the codes and refresh
+/// tokens handed out so far
+pub struct OidcProvider {
+    config: OidcConfig,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Jwk,
+    codes: Mutex<HashMap<String, AuthorizationCode>>,
+    refresh_tokens: Mutex<HashMap<String, RefreshGrant>>,
+}
+
+fn generate_key(algorithm: SigningAlgorithm) -> Result<EncodingKey> {
+    match algorithm {
+        SigningAlgorithm::Rs256 => {
+            use rsa::pkcs1::EncodeRsaPrivateKey;
+            let key = rsa::RsaPrivateKey::new(&mut OsRng, 2048)
+                .map_err(|e| WesterError::Other(format!("RSA key generation failed: {}", e)))?;
+            let der = key
+                .to_pkcs1_der()
+                .map_err(|e| WesterError::Other(format!("RSA key encoding failed: {}", e)))?;
+            Ok(EncodingKey::from_rsa_der(der.as_bytes()))
+        }
+        SigningAlgorithm::Es256 => {
+            use p256::pkcs8::EncodePrivateKey;
+            let der = p256::SecretKey::random(&mut OsRng)
+                .to_pkcs8_der()
+                .map_err(|e| WesterError::Other(format!("EC key encoding failed: {}", e)))?;
+            Ok(EncodingKey::from_ec_der(der.as_bytes()))
+        }
+    }
+}
+
+/// Random URL safe string used for codes and refresh tokens
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcConfig, signing_algorithm: SigningAlgorithm) -> Result<Self> {
+        let algorithm = Algorithm::from(signing_algorithm);
+        let encoding_key = match &config.signing_key_file {
+            Some(path) => {
+                let pem = std::fs::read(path)?;
+                match signing_algorithm {
+                    SigningAlgorithm::Rs256 => EncodingKey::from_rsa_pem(&pem)?,
+                    SigningAlgorithm::Es256 => EncodingKey::from_ec_pem(&pem)?,
+                }
+            }
+            None => {
+                info!(
+                    "Generating {:?} signing key for the OIDC provider",
+                    algorithm
+                );
+                generate_key(signing_algorithm)?
+            }
+        };
+
+        let mut jwk = Jwk::from_encoding_key(&encoding_key, algorithm)?;
+        jwk.common.key_id = Some(jwk.thumbprint(ThumbprintHash::SHA256));
+        let decoding_key = DecodingKey::from_jwk(&jwk)?;
+
+        Ok(Self {
+            config,
+            algorithm,
+            encoding_key,
+            decoding_key,
+            jwk,
+            codes: Mutex::new(HashMap::new()),
+            refresh_tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn issuer(&self, req: &HttpRequest) -> String {
+        self.config.issuer.clone().unwrap_or_else(|| {
+            let conn = req.connection_info();
+            format!("{}://{}", conn.scheme(), conn.host())
+        })
+    }
+
+    fn client(&self, client_id: &str) -> Option<&OidcClient> {
+        self.config
+            .clients
+            .iter()
+            .find(|c| c.client_id == client_id)
+    }
+
+    fn user(&self, username: &str) -> Option<&OidcUser> {
+        self.config.users.iter().find(|u| u.username == username)
+    }
+
+    fn sign(&self, claims: &Map<String, Value>) -> Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.jwk.common.key_id.clone();
+        Ok(encode(&header, claims, &self.encoding_key)?)
+    }
+
+    /// Build the token response for a client, on behalf of a user unless it
+    /// is a client credentials grant
+    fn issue_tokens(
+        &self,
+        issuer: &str,
+        client: &OidcClient,
+        user: Option<&OidcUser>,
+        scope: &str,
+        nonce: Option<&str>,
+        with_refresh_token: bool,
+    ) -> Result<Value> {
+        let now = Utc::now().timestamp();
+        let subject = user.map_or(client.client_id.as_str(), |u| u.username.as_str());
+
+        let mut access = Map::new();
+        access.extend(self.config.claims.clone());
+        access.extend(client.claims.clone());
+        access.insert("iss".to_string(), json!(issuer));
+        access.insert("sub".to_string(), json!(subject));
+        access.insert("aud".to_string(), json!(client.client_id));
+        access.insert("client_id".to_string(), json!(client.client_id));
+        access.insert("scope".to_string(), json!(scope));
+        access.insert("iat".to_string(), json!(now));
+        access.insert("exp".to_string(), json!(now + self.config.access_token_ttl));
+        access.insert("jti".to_string(), json!(random_token()));
+
+        let mut response = json!({
+            "access_token": self.sign(&access)?,
+            "token_type": "Bearer",
+            "expires_in": self.config.access_token_ttl,
+            "scope": scope,
+        });
+
+        if let Some(user) = user {
+            if scope.split(' ').any(|s| s == "openid") {
+                let mut id = Map::new();
+                id.extend(self.config.claims.clone());
+                id.extend(user.claims.clone());
+                id.insert("iss".to_string(), json!(issuer));
+                id.insert("sub".to_string(), json!(subject));
+                id.insert("aud".to_string(), json!(client.client_id));
+                id.insert("iat".to_string(), json!(now));
+                id.insert("auth_time".to_string(), json!(now));
+                id.insert("exp".to_string(), json!(now + self.config.access_token_ttl));
+                if let Some(nonce) = nonce {
+                    id.insert("nonce".to_string(), json!(nonce));
+                }
+                response["id_token"] = json!(self.sign(&id)?);
+            }
+        }
+
+        if with_refresh_token {
+            let refresh_token = random_token();
+            let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+            refresh_tokens.retain(|_, grant| grant.expires_at > now);
+            refresh_tokens.insert(
+                refresh_token.clone(),
+                RefreshGrant {
+                    client_id: client.client_id.clone(),
+                    username: user.map(|u| u.username.clone()),
+                    scope: scope.to_string(),
+                    expires_at: now + self.config.refresh_token_ttl,
+                },
+            );
+            response["refresh_token"] = json!(refresh_token);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Error returned from `/token` as described in RFC 6749 section 5.2
+struct OAuthError {
+    error: &'static str,
+    description: String,
+}
+
+impl OAuthError {
+    fn new(error: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            error,
+            description: description.into(),
+        }
+    }
+
+    fn into_response(self) -> HttpResponse {
+        warn!(
+            "OIDC token request rejected: {} ({})",
+            self.error, self.description
+        );
+        let mut response = if self.error == "invalid_client" {
+            HttpResponse::Unauthorized()
+        } else {
+            HttpResponse::BadRequest()
+        };
+        response
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(json!({
+                "error": self.error,
+                "error_description": self.description,
+            }))
+    }
+}
+
+/// Append query parameters to a redirect URI
+fn redirect_with(redirect_uri: &str, params: &[(&str, Option<&str>)]) -> HttpResponse {
+    let location = match Url::parse(redirect_uri) {
+        Ok(mut url) => {
+            {
+                let mut query = url.query_pairs_mut();
+                for (name, value) in params {
+                    if let Some(value) = value {
+                        query.append_pair(name, value);
+                    }
+                }
+            }
+            url.to_string()
+        }
+        Err(_) => redirect_uri.to_string(),
+    };
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct AuthorizeParams {
+    response_type: Option<String>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    #[serde(flatten)]
+    params: AuthorizeParams,
+    username: Option<String>,
+    password: Option<String>,
+    action: Option<String>,
+}
+
+/// Check client and redirect URI. Errors here must not be redirected to the
+/// client, so they are rendered instead.
+fn validate_client<'a>(
+    provider: &'a OidcProvider,
+    params: &AuthorizeParams,
+) -> std::result::Result<(&'a OidcClient, String), String> {
+    let client_id = params.client_id.as_deref().ok_or("Missing client_id")?;
+    let client = provider
+        .client(client_id)
+        .ok_or_else(|| format!("Unknown client {}", client_id))?;
+    let redirect_uri = params
+        .redirect_uri
+        .clone()
+        .or_else(|| client.redirect_uris.first().cloned())
+        .ok_or("Missing redirect_uri")?;
+    if !client.redirect_uris.is_empty() && !client.redirect_uris.contains(&redirect_uri) {
+        return Err(format!(
+            "Redirect URI {} is not registered for {}",
+            redirect_uri, client_id
+        ));
+    }
+    Ok((client, redirect_uri))
+}
+
+/// Check the parts of the request that are reported back to the client
+fn validate_request(
+    client: &OidcClient,
+    params: &AuthorizeParams,
+) -> Option<(&'static str, &'static str)> {
+    if params.response_type.as_deref() != Some("code") {
+        return Some((
+            "unsupported_response_type",
+            "Only the authorization code flow is supported",
+        ));
+    }
+    if client.client_secret.is_none() && params.code_challenge.is_none() {
+        return Some(("invalid_request", "Public clients must use PKCE"));
+    }
+    if !matches!(
+        params.code_challenge_method.as_deref(),
+        None | Some("S256") | Some("plain")
+    ) {
+        return Some(("invalid_request", "Unsupported code_challenge_method"));
+    }
+    None
+}
+
+fn render_login(client_id: &str, params: &AuthorizeParams, error: Option<&str>) -> Markup {
+    let hidden = [
+        ("response_type", &params.response_type),
+        ("client_id", &params.client_id),
+        ("redirect_uri", &params.redirect_uri),
+        ("scope", &params.scope),
+        ("state", &params.state),
+        ("nonce", &params.nonce),
+        ("code_challenge", &params.code_challenge),
+        ("code_challenge_method", &params.code_challenge_method),
+    ];
+    let scopes = params.scope.as_deref().unwrap_or("openid");
+
+    html! {
+        (DOCTYPE)
+        head {
+            meta charset="utf-8";
+            meta name="viewport" content="width=device-width, initial-scale=1";
+            title { "Sign in" }
+            style { "body{font-family:system-ui,-apple-system,Segoe UI,Roboto,Helvetica,Arial,sans-serif;padding:2rem;} form{max-width:360px;} label{display:block;margin-top:0.75rem;font-weight:600;} input{width:100%;padding:0.4rem;} .error{color:#b91c1c;} button{margin-top:0.75rem;margin-right:0.5rem;padding:0.5rem 1rem;border:1px solid #ccc;border-radius:6px;background:#fff;cursor:pointer;} button:hover{background:#f3f4f6;}" }
+        }
+        body {
+            h1 { "Sign in" }
+            p { strong { (client_id) } " is requesting access to: " code { (scopes) } }
+            @if let Some(error) = error {
+                p class="error" { (error) }
+            }
+            form method="post" action="/authorize" {
+                @for (name, value) in hidden {
+                    @if let Some(value) = value {
+                        input type="hidden" name=(name) value=(value);
+                    }
+                }
+                label for="username" { "Username" }
+                input id="username" name="username" autocomplete="username";
+                label for="password" { "Password" }
+                input id="password" name="password" type="password" autocomplete="current-password";
+                button type="submit" name="action" value="allow" { "Allow" }
+                button type="submit" name="action" value="deny" { "Deny" }
+            }
+        }
+    }
+}
+
+fn html_page(status: actix_web::http::StatusCode, markup: Markup) -> HttpResponse {
+    HttpResponse::build(status)
+        .append_header(header::ContentType::html())
+        .body(markup.into_string())
+}
+
+fn render_error(error: &str) -> HttpResponse {
+    html_page(
+        actix_web::http::StatusCode::BAD_REQUEST,
+        html! {
+            (DOCTYPE)
+            head { meta charset="utf-8"; title { "Authorization error" } }
+            body { h1 { "Authorization error" } p { (error) } }
+        },
+    )
+}
+
+async fn discovery(req: HttpRequest, provider: web::Data<OidcProvider>) -> impl Responder {
+    let issuer = provider.issuer(&req);
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "userinfo_endpoint": format!("{}/userinfo", issuer),
+        "jwks_uri": format!("{}/jwks.json", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "client_credentials", "refresh_token"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": [provider.algorithm],
+        "scopes_supported": ["openid", "profile", "email", "offline_access"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256", "plain"],
+    }))
+}
+
+async fn jwks(provider: web::Data<OidcProvider>) -> impl Responder {
+    HttpResponse::Ok().json(JwkSet {
+        keys: vec![provider.jwk.clone()],
+    })
+}
+
+async fn authorize(
+    params: web::Query<AuthorizeParams>,
+    provider: web::Data<OidcProvider>,
+) -> impl Responder {
+    let (client, redirect_uri) = match validate_client(&provider, &params) {
+        Ok(found) => found,
+        Err(error) => return render_error(&error),
+    };
+    if let Some((error, description)) = validate_request(client, &params) {
+        return redirect_with(
+            &redirect_uri,
+            &[
+                ("error", Some(error)),
+                ("error_description", Some(description)),
+                ("state", params.state.as_deref()),
+            ],
+        );
+    }
+
+    html_page(
+        actix_web::http::StatusCode::OK,
+        render_login(&client.client_id, &params, None),
+    )
+}
+
+async fn authorize_submit(
+    form: web::Form<LoginForm>,
+    provider: web::Data<OidcProvider>,
+) -> impl Responder {
+    let form = form.into_inner();
+    let params = &form.params;
+    let (client, redirect_uri) = match validate_client(&provider, params) {
+        Ok(found) => found,
+        Err(error) => return render_error(&error),
+    };
+    if let Some((error, description)) = validate_request(client, params) {
+        return redirect_with(
+            &redirect_uri,
+            &[
+                ("error", Some(error)),
+                ("error_description", Some(description)),
+                ("state", params.state.as_deref()),
+            ],
+        );
+    }
+
+    if form.action.as_deref() == Some("deny") {
+        info!("OIDC authorization denied for client {}", client.client_id);
+        return redirect_with(
+            &redirect_uri,
+            &[
+                ("error", Some("access_denied")),
+                ("state", params.state.as_deref()),
+            ],
+        );
+    }
+
+    let username = form.username.as_deref().unwrap_or_default();
+    let authenticated = provider
+        .user(username)
+        .zip(form.password.as_deref())
+        .is_some_and(|(user, password)| {
+            constant_time_eq(user.password.as_bytes(), password.as_bytes())
+        });
+    if !authenticated {
+        warn!("OIDC login failed for user {:?}", username);
+        return html_page(
+            actix_web::http::StatusCode::UNAUTHORIZED,
+            render_login(
+                &client.client_id,
+                params,
+                Some("Invalid username or password"),
+            ),
+        );
+    }
+
+    let code = random_token();
+    let now = Utc::now().timestamp();
+    {
+        let mut codes = provider.codes.lock().unwrap();
+        codes.retain(|_, c| c.expires_at > now);
+        codes.insert(
+            code.clone(),
+            AuthorizationCode {
+                client_id: client.client_id.clone(),
+                redirect_uri: redirect_uri.clone(),
+                username: username.to_string(),
+                scope: params.scope.clone().unwrap_or_else(|| "openid".to_string()),
+                nonce: params.nonce.clone(),
+                code_challenge: params.code_challenge.clone(),
+                code_challenge_method: params.code_challenge_method.clone(),
+                expires_at: now + provider.config.code_ttl,
+            },
+        );
+    }
+    info!(
+        "OIDC user {} authorized client {}",
+        username, client.client_id
+    );
+
+    redirect_with(
+        &redirect_uri,
+        &[("code", Some(&code)), ("state", params.state.as_deref())],
+    )
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    grant_type: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Credentials from `Authorization: Basic` or from the form body
+fn client_credentials(req: &HttpRequest, form: &TokenRequest) -> Option<(String, Option<String>)> {
+    let basic = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| STANDARD.decode(v.trim()).ok())
+        .and_then(|v| String::from_utf8(v).ok())
+        .and_then(|v| {
+            v.split_once(':')
+                .map(|(id, secret)| (id.to_string(), Some(secret.to_string())))
+        });
+
+    basic.or_else(|| {
+        form.client_id
+            .clone()
+            .map(|id| (id, form.client_secret.clone()))
+    })
+}
+
+fn authenticate_client<'a>(
+    provider: &'a OidcProvider,
+    req: &HttpRequest,
+    form: &TokenRequest,
+) -> std::result::Result<&'a OidcClient, OAuthError> {
+    let (client_id, secret) = client_credentials(req, form)
+        .ok_or_else(|| OAuthError::new("invalid_client", "Missing client credentials"))?;
+    let client = provider
+        .client(&client_id)
+        .ok_or_else(|| OAuthError::new("invalid_client", "Unknown client"))?;
+
+    match &client.client_secret {
+        Some(expected)
+            if !secret
+                .as_ref()
+                .is_some_and(|secret| constant_time_eq(expected.as_bytes(), secret.as_bytes())) =>
+        {
+            Err(OAuthError::new("invalid_client", "Invalid client secret"))
+        }
+        _ => Ok(client),
+    }
+}
+
+fn verify_pkce(grant: &AuthorizationCode, verifier: Option<&str>) -> bool {
+    match (&grant.code_challenge, verifier) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(challenge), Some(verifier)) => match grant.code_challenge_method.as_deref() {
+            // Without a method the challenge is plain, RFC 7636 section 4.3
+            None | Some("plain") => challenge == verifier,
+            Some("S256") => {
+                URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == *challenge
+            }
+            Some(_) => false,
+        },
+    }
+}
+
+fn exchange_token(
+    provider: &OidcProvider,
+    req: &HttpRequest,
+    form: &TokenRequest,
+) -> std::result::Result<Value, OAuthError> {
+    let client = authenticate_client(provider, req, form)?;
+    let issuer = provider.issuer(req);
+    let now = Utc::now().timestamp();
+    let server_error = |e: WesterError| OAuthError::new("server_error", e.to_string());
+
+    match form.grant_type.as_deref() {
+        Some("authorization_code") => {
+            let code = form.code.as_deref().unwrap_or_default();
+            let grant = provider
+                .codes
+                .lock()
+                .unwrap()
+                .remove(code)
+                .filter(|g| g.expires_at > now && g.client_id == client.client_id)
+                .ok_or_else(|| OAuthError::new("invalid_grant", "Invalid or expired code"))?;
+            if form.redirect_uri.as_deref() != Some(grant.redirect_uri.as_str()) {
+                return Err(OAuthError::new("invalid_grant", "redirect_uri mismatch"));
+            }
+            if !verify_pkce(&grant, form.code_verifier.as_deref()) {
+                return Err(OAuthError::new("invalid_grant", "PKCE verification failed"));
+            }
+            let user = provider
+                .user(&grant.username)
+                .ok_or_else(|| OAuthError::new("invalid_grant", "Unknown user"))?;
+
+            provider
+                .issue_tokens(
+                    &issuer,
+                    client,
+                    Some(user),
+                    &grant.scope,
+                    grant.nonce.as_deref(),
+                    true,
+                )
+                .map_err(server_error)
+        }
+        Some("client_credentials") => {
+            if client.client_secret.is_none() {
+                return Err(OAuthError::new(
+                    "unauthorized_client",
+                    "Public clients can't use client credentials",
+                ));
+            }
+            let scope = form.scope.clone().unwrap_or_default();
+            provider
+                .issue_tokens(&issuer, client, None, &scope, None, false)
+                .map_err(server_error)
+        }
+        Some("refresh_token") => {
+            let token = form.refresh_token.as_deref().unwrap_or_default();
+            let grant = provider
+                .refresh_tokens
+                .lock()
+                .unwrap()
+                .remove(token)
+                .filter(|g| g.expires_at > now && g.client_id == client.client_id)
+                .ok_or_else(|| {
+                    OAuthError::new("invalid_grant", "Invalid or expired refresh token")
+                })?;
+            let user = grant
+                .username
+                .as_deref()
+                .and_then(|name| provider.user(name));
+
+            provider
+                .issue_tokens(&issuer, client, user, &grant.scope, None, true)
+                .map_err(server_error)
+        }
+        Some(other) => Err(OAuthError::new(
+            "unsupported_grant_type",
+            format!("Unsupported grant type {}", other),
+        )),
+        None => Err(OAuthError::new("invalid_request", "Missing grant_type")),
+    }
+}
+
+async fn token(
+    req: HttpRequest,
+    form: web::Form<TokenRequest>,
+    provider: web::Data<OidcProvider>,
+) -> impl Responder {
+    debug!("OIDC token request with grant {:?}", form.grant_type);
+    match exchange_token(&provider, &req, &form) {
+        Ok(tokens) => HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(tokens),
+        Err(error) => error.into_response(),
+    }
+}
+
+async fn userinfo(req: HttpRequest, provider: web::Data<OidcProvider>) -> impl Responder {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    let mut validation = Validation::new(provider.algorithm);
+    validation.validate_aud = false;
+    let claims = token.and_then(|token| {
+        decode::<Map<String, Value>>(token, &provider.decoding_key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| debug!("Rejected userinfo token: {}", e))
+            .ok()
+    });
+
+    match claims {
+        Some(claims) => {
+            let subject = claims["sub"].as_str().unwrap_or_default();
+            let mut info = provider
+                .user(subject)
+                .map(|user| user.claims.clone())
+                .unwrap_or_default();
+            info.insert("sub".to_string(), json!(subject));
+            HttpResponse::Ok().json(info)
+        }
+        None => HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\""))
+            .finish(),
+    }
+}
+
+/// Register the mock provider routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/.well-known/openid-configuration",
+        web::get().to(discovery),
+    )
+    .route("/jwks.json", web::get().to(jwks))
+    .route("/authorize", web::get().to(authorize))
+    .route("/authorize", web::post().to(authorize_submit))
+    .route("/token", web::post().to(token))
+    .route("/userinfo", web::get().to(userinfo))
+    .route("/userinfo", web::post().to(userinfo));
+}
@@ -0,0 +1,354 @@
+use crate::add_connection_detail;
+use crate::server::stream::Peekable;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncRead;
+
+/// Signature opening every v2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest v1 header allowed by the specification, CRLF included
+const V1_MAX_LEN: usize = 107;
+
+/// Time a client has to send the header
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether connections must, may or must not start with a PROXY header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProxyProtocolMode {
+    #[default]
+    Off,
+    Optional,
+    Required,
+}
+
+/// Type-length-value field of a v2 header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tlv {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+/// A parsed PROXY protocol header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub version: u8,
+    /// `PROXY` for relayed connections, `LOCAL` for the proxy's own, e.g.
+    /// health checks
+    pub command: &'static str,
+    pub protocol: &'static str,
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+    pub tlvs: Vec<Tlv>,
+}
+
+/// The PROXY header a connection started with, and the address of the
+/// proxy that sent it
+#[derive(Clone)]
+pub struct ProxyConnection {
+    pub header: ProxyHeader,
+    pub peer: Option<SocketAddr>,
+}
+
+enum Detect {
+    Incomplete,
+    Missing,
+    Length(usize),
+}
+
+fn invalid(msg: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Length of the header the buffer starts with, if it starts with one
+fn detect(buf: &[u8]) -> io::Result<Detect> {
+    if buf.starts_with(&V2_SIGNATURE) {
+        if buf.len() < 16 {
+            return Ok(Detect::Incomplete);
+        }
+        Ok(Detect::Length(
+            16 + u16::from_be_bytes([buf[14], buf[15]]) as usize,
+        ))
+    } else if buf.starts_with(b"PROXY ") {
+        match buf.windows(2).position(|w| w == b"\r\n") {
+            Some(pos) if pos + 2 <= V1_MAX_LEN => Ok(Detect::Length(pos + 2)),
+            None if buf.len() < V1_MAX_LEN => Ok(Detect::Incomplete),
+            _ => Err(invalid("PROXY v1 header longer than 107 bytes")),
+        }
+    } else if V2_SIGNATURE.starts_with(buf) || b"PROXY ".starts_with(buf) {
+        Ok(Detect::Incomplete)
+    } else {
+        Ok(Detect::Missing)
+    }
+}
+
+/// Parse a v1 header, e.g. `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`
+pub fn parse_v1(header: &[u8]) -> io::Result<ProxyHeader> {
+    let line = std::str::from_utf8(header)
+        .ok()
+        .and_then(|line| line.strip_suffix("\r\n"))
+        .ok_or_else(|| invalid("PROXY v1 header isn't an ASCII line"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    let protocol = match fields.get(1) {
+        Some(&"TCP4") => "TCP4",
+        Some(&"TCP6") => "TCP6",
+        // Anything may follow UNKNOWN, and must be ignored
+        Some(&"UNKNOWN") => {
+            return Ok(ProxyHeader {
+                version: 1,
+                command: "PROXY",
+                protocol: "UNKNOWN",
+                source: None,
+                destination: None,
+                tlvs: Vec::new(),
+            })
+        }
+        _ => return Err(invalid(format!("Invalid PROXY v1 header {:?}", line))),
+    };
+    let [_, _, src, dst, src_port, dst_port] = fields.as_slice() else {
+        return Err(invalid(format!("Invalid PROXY v1 header {:?}", line)));
+    };
+    let address = |ip: &str, port: &str| -> io::Result<SocketAddr> {
+        let ip: IpAddr = ip
+            .parse()
+            .map_err(|_| invalid(format!("Invalid PROXY v1 address {}", ip)))?;
+        if ip.is_ipv4() != (protocol == "TCP4") {
+            return Err(invalid(format!("{} address {} in PROXY v1", protocol, ip)));
+        }
+        let port = port
+            .parse()
+            .map_err(|_| invalid(format!("Invalid PROXY v1 port {}", port)))?;
+        Ok(SocketAddr::new(ip, port))
+    };
+
+    Ok(ProxyHeader {
+        version: 1,
+        command: "PROXY",
+        protocol,
+        source: Some(address(src, src_port)?),
+        destination: Some(address(dst, dst_port)?),
+        tlvs: Vec::new(),
+    })
+}
+
+fn parse_tlvs(mut data: &[u8]) -> io::Result<Vec<Tlv>> {
+    let mut tlvs = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 3 {
+            return Err(invalid("Truncated PROXY v2 TLV"));
+        }
+        let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+        let value = data
+            .get(3..3 + len)
+            .ok_or_else(|| invalid("Truncated PROXY v2 TLV"))?;
+        tlvs.push(Tlv {
+            kind: data[0],
+            value: value.to_vec(),
+        });
+        data = &data[3 + len..];
+    }
+
+    Ok(tlvs)
+}
+
+/// Parse a v2 header, the signature, the fixed part, the addresses and TLVs
+pub fn parse_v2(header: &[u8]) -> io::Result<ProxyHeader> {
+    if header.len() < 16 || !header.starts_with(&V2_SIGNATURE) {
+        return Err(invalid("Truncated PROXY v2 header"));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(invalid(format!(
+            "Unsupported PROXY version {}",
+            header[12] >> 4
+        )));
+    }
+    let command = match header[12] & 0x0f {
+        0 => "LOCAL",
+        1 => "PROXY",
+        command => return Err(invalid(format!("Unknown PROXY v2 command {}", command))),
+    };
+    let body = &header[16..];
+    let (protocol, address_len) = match header[13] {
+        0x00 => ("UNKNOWN", 0),
+        0x11 => ("TCP4", 12),
+        0x12 => ("UDP4", 12),
+        0x21 => ("TCP6", 36),
+        0x22 => ("UDP6", 36),
+        0x31 => ("UNIX", 216),
+        0x32 => ("UNIX_DGRAM", 216),
+        family => {
+            return Err(invalid(format!(
+                "Unknown PROXY v2 address family {:#04x}",
+                family
+            )))
+        }
+    };
+    if body.len() < address_len {
+        return Err(invalid("Truncated PROXY v2 addresses"));
+    }
+
+    let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+    let (source, destination) = match address_len {
+        12 => {
+            let ip = |at: usize| Ipv4Addr::new(body[at], body[at + 1], body[at + 2], body[at + 3]);
+            (
+                Some(SocketAddr::new(ip(0).into(), port(8))),
+                Some(SocketAddr::new(ip(4).into(), port(10))),
+            )
+        }
+        36 => {
+            let ip = |at: usize| {
+                let octets: [u8; 16] = body[at..at + 16].try_into().unwrap();
+                Ipv6Addr::from(octets)
+            };
+            (
+                Some(SocketAddr::new(ip(0).into(), port(32))),
+                Some(SocketAddr::new(ip(16).into(), port(34))),
+            )
+        }
+        // Unix socket paths can't be told as socket addresses
+        _ => (None, None),
+    };
+
+    Ok(ProxyHeader {
+        version: 2,
+        command,
+        protocol,
+        // The addresses of LOCAL connections must be ignored
+        source: source.filter(|_| command == "PROXY"),
+        destination: destination.filter(|_| command == "PROXY"),
+        tlvs: parse_tlvs(&body[address_len..])?,
+    })
+}
+
+/// Read the PROXY header the stream starts with, consuming nothing else. The
+/// bytes read ahead of a connection without one are left for HTTP
+pub async fn read_header<T: AsyncRead + Unpin>(
+    io: &mut Peekable<T>,
+    mode: ProxyProtocolMode,
+) -> io::Result<Option<ProxyHeader>> {
+    actix_web::rt::time::timeout(HEADER_TIMEOUT, peek_header(io, mode))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "PROXY header timed out"))?
+}
+
+async fn peek_header<T: AsyncRead + Unpin>(
+    io: &mut Peekable<T>,
+    mode: ProxyProtocolMode,
+) -> io::Result<Option<ProxyHeader>> {
+    loop {
+        let len = match detect(io.buffered())? {
+            Detect::Incomplete => V1_MAX_LEN,
+            Detect::Missing if mode == ProxyProtocolMode::Required => {
+                return Err(invalid("Connection without PROXY header"))
+            }
+            Detect::Missing => return Ok(None),
+            Detect::Length(len) if io.buffered().len() >= len => {
+                let header = io.buffered()[..len].to_vec();
+                io.consume(len);
+                return if header.starts_with(&V2_SIGNATURE) {
+                    parse_v2(&header).map(Some)
+                } else {
+                    parse_v1(&header).map(Some)
+                };
+            }
+            Detect::Length(len) => len,
+        };
+        if io.fill(len).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
+
+fn tlv_name(kind: u8) -> &'static str {
+    match kind {
+        0x01 => "ALPN",
+        0x02 => "AUTHORITY",
+        0x03 => "CRC32C",
+        0x04 => "NOOP",
+        0x05 => "UNIQUE_ID",
+        0x20 => "SSL",
+        0x21 => "SSL_VERSION",
+        0x22 => "SSL_CN",
+        0x23 => "SSL_CIPHER",
+        0x24 => "SSL_SIG_ALG",
+        0x25 => "SSL_KEY_ALG",
+        0x30 => "NETNS",
+        0xea => "AWS",
+        0xee => "AZURE",
+        0xe0..=0xef => "CUSTOM",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Printable values are shown as text, the others as hex
+fn tlv_value(value: &[u8]) -> Value {
+    match std::str::from_utf8(value) {
+        Ok(text) if !text.chars().any(char::is_control) => json!(text),
+        _ => json!(value
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()),
+    }
+}
+
+fn tlv_report(tlv: &Tlv) -> Value {
+    let mut report = json!({
+        "type": tlv.kind,
+        "name": tlv_name(tlv.kind),
+        "value": tlv_value(&tlv.value),
+    });
+    match (tlv.kind, tlv.value.as_slice()) {
+        // SSL: client flags, verify result, then sub TLVs
+        (0x20, [client, v0, v1, v2, v3, sub @ ..]) => {
+            report["client"] = json!(client);
+            report["verify"] = json!(u32::from_be_bytes([*v0, *v1, *v2, *v3]));
+            if let Ok(sub) = parse_tlvs(sub) {
+                report["tlvs"] = sub.iter().map(tlv_report).collect();
+            }
+        }
+        // AWS: subtype 0x01 is the VPC endpoint ID
+        (0xea, [0x01, id @ ..]) => {
+            report["vpce_id"] = tlv_value(id);
+        }
+        _ => {}
+    }
+
+    report
+}
+
+impl ProxyConnection {
+    pub fn report(&self) -> Value {
+        json!({
+            "version": self.header.version,
+            "command": self.header.command,
+            "protocol": self.header.protocol,
+            "source": self.header.source.map(|a| a.to_string()),
+            "destination": self.header.destination.map(|a| a.to_string()),
+            "proxy": self.peer.map(|a| a.to_string()),
+            "tlvs": self.header.tlvs.iter().map(tlv_report).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Add the PROXY header of the connection to every response rendered by
+/// `prepare_response`
+pub async fn proxy_protocol(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(connection) = req.request().conn_data::<ProxyConnection>() {
+        add_connection_detail(req.request(), "proxy_protocol", connection.report());
+    }
+
+    next.call(req).await
+}
@@ -0,0 +1,104 @@
+use crate::routes::cache::LAST_MODIFIED;
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::header::{self, ContentType, EntityTag, HttpDate, IfRange, Range};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, Error, HttpMessage, HttpRequest, HttpResponse};
+use log::debug;
+use std::time::SystemTime;
+
+/// Largest body served by `/range/{n}`
+const MAX_RANGE_SIZE: u64 = 100 * 1024;
+
+/// Boundary of `multipart/byteranges` responses, the body only holds letters
+/// so it can't collide with it
+const BOUNDARY: &str = "rustwester-byteranges";
+
+/// The same `n` bytes are always served: the alphabet, repeated
+fn range_body(n: u64) -> Vec<u8> {
+    (0..n).map(|i| b'a' + (i % 26) as u8).collect()
+}
+
+/// `If-Range` only lets the `Range` apply when the validator still matches
+fn if_range_matches(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match req.get_header::<IfRange>() {
+        None => true,
+        Some(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+        Some(IfRange::Date(date)) => SystemTime::from(date) == *LAST_MODIFIED,
+    }
+}
+
+/// Serve `n` deterministic bytes, honouring `Range` and `If-Range`, with
+/// `multipart/byteranges` responses for several ranges
+#[get("/range/{n}")]
+pub async fn range(req: HttpRequest, n: web::Path<u64>) -> Result<HttpResponse, Error> {
+    let n = n.into_inner();
+    if n > MAX_RANGE_SIZE {
+        return Err(ErrorBadRequest(format!(
+            "Range size {} is larger than the {} bytes limit",
+            n, MAX_RANGE_SIZE
+        )));
+    }
+
+    let body = range_body(n);
+    let etag = EntityTag::new_strong(format!("range-{}", n));
+    let mut response = HttpResponse::Ok();
+    // Ranges apply to the bytes as sent, so they must not be compressed
+    response
+        .insert_header(header::ContentEncoding::Identity)
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::ETAG, etag.clone()))
+        .insert_header((header::LAST_MODIFIED, HttpDate::from(*LAST_MODIFIED)));
+
+    // Malformed or other unit ranges are ignored, as RFC 9110 allows
+    let specs = match req.get_header::<Range>() {
+        Some(Range::Bytes(specs)) if if_range_matches(&req, &etag) => specs,
+        _ => {
+            return Ok(response
+                .insert_header(ContentType::octet_stream())
+                .body(body))
+        }
+    };
+
+    let ranges: Vec<(u64, u64)> = specs
+        .iter()
+        .filter_map(|spec| spec.to_satisfiable_range(n))
+        .collect();
+    debug!("Range {:?} of {} bytes satisfied by {:?}", specs, n, ranges);
+
+    match ranges.as_slice() {
+        [] => Ok(HttpResponse::RangeNotSatisfiable()
+            .insert_header((header::CONTENT_RANGE, format!("bytes */{}", n)))
+            .finish()),
+        [(start, end)] => Ok(response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .insert_header(ContentType::octet_stream())
+            .insert_header((
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, n),
+            ))
+            .body(body[*start as usize..=*end as usize].to_vec())),
+        ranges => {
+            let mut multipart = Vec::new();
+            for (start, end) in ranges {
+                multipart.extend_from_slice(
+                    format!(
+                        "--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        BOUNDARY, start, end, n
+                    )
+                    .as_bytes(),
+                );
+                multipart.extend_from_slice(&body[*start as usize..=*end as usize]);
+                multipart.extend_from_slice(b"\r\n");
+            }
+            multipart.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+
+            Ok(response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .insert_header((
+                    header::CONTENT_TYPE,
+                    format!("multipart/byteranges; boundary={}", BOUNDARY),
+                ))
+                .body(multipart))
+        }
+    }
+}
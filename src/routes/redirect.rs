@@ -0,0 +1,149 @@
+use crate::{prepare_response, wants_json, AppState};
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use log::debug;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Default number of hops after which `/redirect-loop` gives up
+const DEFAULT_LOOP_MAX: u32 = 20;
+
+#[derive(Deserialize)]
+pub struct RedirectQuery {
+    json: Option<String>,
+    absolute: Option<String>,
+    status_code: Option<u16>,
+}
+
+#[derive(Deserialize)]
+pub struct RedirectToQuery {
+    url: String,
+    status_code: Option<u16>,
+}
+
+#[derive(Deserialize)]
+pub struct RedirectLoopQuery {
+    json: Option<String>,
+    max: Option<u32>,
+    hop: Option<u32>,
+    status_code: Option<u16>,
+}
+
+/// Only the status codes meant to carry a `Location` header are accepted,
+/// defaulting to 302
+fn redirect_status(status_code: Option<u16>) -> Result<StatusCode, Error> {
+    match status_code.unwrap_or(302) {
+        code @ (301 | 302 | 303 | 307 | 308) => Ok(StatusCode::from_u16(code).unwrap()),
+        code => Err(ErrorBadRequest(format!(
+            "Invalid redirect status code {}, expected 301, 302, 303, 307 or 308",
+            code
+        ))),
+    }
+}
+
+fn redirect(status: StatusCode, location: &str) -> Result<HttpResponse, Error> {
+    let location = HeaderValue::from_str(location)
+        .map_err(|_| ErrorBadRequest(format!("Invalid redirect location {}", location)))?;
+    debug!("Redirecting with {} to {:?}", status, location);
+
+    Ok(HttpResponse::build(status)
+        .insert_header((header::LOCATION, location))
+        .finish())
+}
+
+/// Prefix `path` with the scheme and host the client used, as seen through
+/// `Forwarded` or `X-Forwarded-Proto`/`X-Forwarded-Host` when behind a proxy
+fn absolute_url(req: &HttpRequest, path: &str) -> String {
+    let info = req.connection_info();
+    format!("{}://{}{}", info.scheme(), info.host(), path)
+}
+
+fn with_query(path: String, query: &str) -> String {
+    if query.is_empty() {
+        path
+    } else {
+        format!("{}?{}", path, query)
+    }
+}
+
+/// Redirect `n` times before answering, e.g. `/redirect/3` goes through
+/// `/redirect/2`, `/redirect/1` and ends on `/redirect/0`. The query string is
+/// kept along the way, `?absolute` switches to absolute `Location` URLs
+async fn redirect_n(
+    req: HttpRequest,
+    path: web::Path<u32>,
+    query: web::Query<RedirectQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let hops = path.into_inner();
+    let status = redirect_status(query.status_code)?;
+
+    if hops == 0 {
+        let info = req.connection_info().clone();
+        return Ok(prepare_response(
+            &req,
+            wants_json(&req, query.json.is_some(), &data),
+            Some("Redirects done"),
+            Some(json!({
+                "scheme": info.scheme(),
+                "host": info.host(),
+            })),
+        )
+        .await);
+    }
+
+    let location = with_query(format!("/redirect/{}", hops - 1), req.query_string());
+    if query.absolute.is_some() {
+        redirect(status, &absolute_url(&req, &location))
+    } else {
+        redirect(status, &location)
+    }
+}
+
+/// Redirect to any URL, e.g. `/redirect-to?url=https://example.com&status_code=307`
+async fn redirect_to(query: web::Query<RedirectToQuery>) -> Result<HttpResponse, Error> {
+    redirect(redirect_status(query.status_code)?, &query.url)
+}
+
+/// Redirect to itself, counting the hops in the query string, until `max`
+/// hops are reached and a 508 Loop Detected is returned
+async fn redirect_loop(
+    req: HttpRequest,
+    query: web::Query<RedirectLoopQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let status = redirect_status(query.status_code)?;
+    let max = query.max.unwrap_or(DEFAULT_LOOP_MAX);
+    let hop = query.hop.unwrap_or(0);
+
+    if hop >= max {
+        let mut response = prepare_response(
+            &req,
+            wants_json(&req, query.json.is_some(), &data),
+            Some("Redirect loop detected"),
+            Some(json!({ "hops": hop, "max": max })),
+        )
+        .await;
+        *response.status_mut() = StatusCode::LOOP_DETECTED;
+        return Ok(response);
+    }
+
+    let mut params = vec![format!("max={}", max), format!("hop={}", hop + 1)];
+    if let Some(status_code) = query.status_code {
+        params.push(format!("status_code={}", status_code));
+    }
+    if query.json.is_some() {
+        params.push("json".to_string());
+    }
+    redirect(status, &format!("/redirect-loop?{}", params.join("&")))
+}
+
+/// Register the redirect routes, answering every method so 307 and 308 can be
+/// checked with non `GET` requests
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/redirect/{n}", web::to(redirect_n))
+        .route("/redirect-to", web::to(redirect_to))
+        .route("/redirect-loop", web::to(redirect_loop));
+}
@@ -0,0 +1,76 @@
+use super::client_ip::TrustedProxies;
+use super::config::EffectiveConfig;
+use super::cookies::StickyCookie;
+use super::cors::CorsConfig;
+use super::download::MaxBandwidth;
+use super::limits::BodyLimits;
+use crate::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+/// App data replaced as a whole when the configuration is reloaded, settings
+/// left out being disabled
+pub struct Settings {
+    pub state: web::Data<AppState>,
+    pub body_limits: web::Data<BodyLimits>,
+    pub trusted_proxies: web::Data<TrustedProxies>,
+    pub max_bandwidth: Option<web::Data<MaxBandwidth>>,
+    pub cors: Option<web::Data<CorsConfig>>,
+    pub sticky_cookie: Option<web::Data<StickyCookie>>,
+    pub config: web::Data<EffectiveConfig>,
+}
+
+impl Settings {
+    fn data(&self) -> Extensions {
+        let mut data = Extensions::new();
+        data.insert(self.state.clone());
+        data.insert(self.body_limits.clone());
+        data.insert(self.trusted_proxies.clone());
+        data.insert(self.config.clone());
+        if let Some(max_bandwidth) = &self.max_bandwidth {
+            data.insert(max_bandwidth.clone());
+        }
+        if let Some(cors) = &self.cors {
+            data.insert(cors.clone());
+        }
+        if let Some(sticky_cookie) = &self.sticky_cookie {
+            data.insert(sticky_cookie.clone());
+        }
+        data
+    }
+}
+
+/// The settings in effect, read once per request so a reload never applies
+/// halfway through one
+pub struct Reloadable(RwLock<Arc<Settings>>);
+
+impl Reloadable {
+    pub fn new(settings: Settings) -> Self {
+        Self(RwLock::new(Arc::new(settings)))
+    }
+
+    pub fn current(&self) -> Arc<Settings> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, settings: Settings) {
+        *self.0.write().unwrap() = Arc::new(settings);
+    }
+}
+
+/// Make the current settings the app data of the request, ahead of the data
+/// registered with the app. Wrapped last, before any other middleware runs
+pub async fn apply_settings(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(reloadable) = req.app_data::<web::Data<Reloadable>>() {
+        let data = reloadable.current().data();
+        req.add_data_container(Rc::new(data));
+    }
+    next.call(req).await
+}
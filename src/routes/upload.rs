@@ -0,0 +1,125 @@
+use crate::{prepare_response, wants_json, AppState, RequestInfo};
+use actix_multipart::Multipart;
+use actix_web::{route, web, Error, HttpMessage, HttpRequest, Responder};
+use futures_util::StreamExt;
+use log::debug;
+use md5::Md5;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Running size and hashes of a body, fed chunk by chunk
+struct BodyDigest {
+    size: u64,
+    chunks: u64,
+    sha256: Sha256,
+    md5: Md5,
+    crc32: crc32fast::Hasher,
+}
+
+impl BodyDigest {
+    fn new() -> Self {
+        Self {
+            size: 0,
+            chunks: 0,
+            sha256: Sha256::new(),
+            md5: Md5::new(),
+            crc32: crc32fast::Hasher::new(),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        self.size += chunk.len() as u64;
+        self.chunks += 1;
+        self.sha256.update(chunk);
+        self.md5.update(chunk);
+        self.crc32.update(chunk);
+    }
+
+    fn report(self) -> Value {
+        json!({
+            "size": self.size,
+            "chunks": self.chunks,
+            "sha256": hex(&self.sha256.finalize()),
+            "md5": hex(&self.md5.finalize()),
+            "crc32": format!("{:08x}", self.crc32.finalize()),
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Stream the request body, without buffering it, and report its size and
+/// hashes. Files of `multipart/form-data` bodies are also reported one by one
+#[route("/upload", method = "PUT", method = "POST")]
+pub async fn upload(
+    req: HttpRequest,
+    payload: web::Payload,
+    info: web::Query<RequestInfo>,
+    data: web::Data<AppState>,
+) -> Result<impl Responder, Error> {
+    let started = Instant::now();
+    let body = Rc::new(RefCell::new(BodyDigest::new()));
+    let digest = body.clone();
+    let mut payload = payload.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            digest.borrow_mut().update(chunk);
+        }
+    });
+
+    let mut files = Vec::new();
+    let is_multipart = req
+        .mime_type()
+        .ok()
+        .flatten()
+        .is_some_and(|mime| mime.essence_str() == "multipart/form-data");
+    if is_multipart {
+        let mut multipart = Multipart::new(req.headers(), payload);
+        while let Some(field) = multipart.next().await {
+            let mut field = field?;
+            let mut file = BodyDigest::new();
+            while let Some(chunk) = field.next().await {
+                file.update(&chunk?);
+            }
+            let mut report = file.report();
+            report["field"] = json!(field.name());
+            report["filename"] =
+                json!(field.content_disposition().and_then(|cd| cd.get_filename()));
+            report["content_type"] = json!(field.content_type().map(|m| m.to_string()));
+            files.push(report);
+        }
+    } else {
+        while let Some(chunk) = payload.next().await {
+            chunk?;
+        }
+    }
+
+    let duration = started.elapsed();
+    let digest = body.replace(BodyDigest::new());
+    let size = digest.size;
+    debug!("Received {} bytes in {:?}", size, duration);
+
+    let mut report = digest.report();
+    report["content_type"] = json!(req.content_type());
+    report["duration_ms"] = json!(duration.as_secs_f64() * 1000.0);
+    report["throughput_bytes_per_second"] = json!(if duration.is_zero() {
+        0.0
+    } else {
+        size as f64 / duration.as_secs_f64()
+    });
+    if is_multipart {
+        report["files"] = json!(files);
+    }
+
+    Ok(prepare_response(
+        &req,
+        wants_json(&req, info.json.is_some(), &data),
+        Some("Upload received"),
+        Some(report),
+    )
+    .await)
+}
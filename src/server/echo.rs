@@ -0,0 +1,134 @@
+use crate::get_hostname;
+use log::{debug, info, warn};
+use std::future::Future;
+use std::io;
+use std::net::{self, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+/// Largest UDP payload
+const DATAGRAM_SIZE: usize = 65535;
+
+/// Pause after an error that isn't caused by a single peer, such as running
+/// out of file descriptors, which would otherwise repeat at once
+const ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Errors caused by a single peer, after which the listener can go on at once
+fn is_peer_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::Interrupted
+    )
+}
+
+/// Log an error of a listener, which keeps serving after it
+async fn recover(listener: &str, err: io::Error) {
+    warn!("{} echo failed to receive: {}", listener, err);
+    if !is_peer_error(&err) {
+        tokio::time::sleep(ERROR_BACKOFF).await;
+    }
+}
+
+/// Prefix of every echoed payload, when asked for
+async fn prefix(enabled: bool, peer: SocketAddr) -> Vec<u8> {
+    match enabled {
+        true => format!("{} {} ", get_hostname().await, peer).into_bytes(),
+        false => Vec::new(),
+    }
+}
+
+async fn echo_connection(mut io: TcpStream, peer: SocketAddr, with_prefix: bool) {
+    let started = Instant::now();
+    let prefix = prefix(with_prefix, peer).await;
+    let mut buf = vec![0; 16 * 1024];
+    let mut total = 0;
+    let result = loop {
+        let n = match io.read(&mut buf).await {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(err) => break Err(err),
+        };
+        total += n;
+        let written = match prefix.is_empty() {
+            true => io.write_all(&buf[..n]).await,
+            false => io.write_all(&[&prefix, &buf[..n]].concat()).await,
+        };
+        if let Err(err) = written {
+            break Err(err);
+        }
+    };
+
+    match result {
+        Ok(()) => info!(
+            "TCP echo connection from {} closed after {:?}, {} bytes echoed",
+            peer,
+            started.elapsed(),
+            total
+        ),
+        Err(err) => info!(
+            "TCP echo connection from {} failed after {:?}, {} bytes echoed: {}",
+            peer,
+            started.elapsed(),
+            total,
+            err
+        ),
+    }
+}
+
+/// Echo back what every connection accepted on `lst` sends
+pub fn tcp(
+    lst: net::TcpListener,
+    with_prefix: bool,
+) -> io::Result<impl Future<Output = io::Result<()>>> {
+    lst.set_nonblocking(true)?;
+    let lst = TcpListener::from_std(lst)?;
+    info!("TCP echo listening on {}", lst.local_addr()?);
+
+    Ok(async move {
+        loop {
+            let (io, peer) = match lst.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    recover("TCP", err).await;
+                    continue;
+                }
+            };
+            info!("TCP echo connection from {}", peer);
+            tokio::spawn(echo_connection(io, peer, with_prefix));
+        }
+    })
+}
+
+/// Send every datagram received on `socket` back to where it came from
+pub fn udp(
+    socket: net::UdpSocket,
+    with_prefix: bool,
+) -> io::Result<impl Future<Output = io::Result<()>>> {
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket)?;
+    info!("UDP echo listening on {}", socket.local_addr()?);
+
+    Ok(async move {
+        let mut buf = vec![0; DATAGRAM_SIZE];
+        loop {
+            let (n, peer) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(err) => {
+                    recover("UDP", err).await;
+                    continue;
+                }
+            };
+            debug!("UDP echo datagram of {} bytes from {}", n, peer);
+            let mut datagram = prefix(with_prefix, peer).await;
+            datagram.extend_from_slice(&buf[..n]);
+            // A datagram that can't be sent back must not stop the listener
+            if let Err(err) = socket.send_to(&datagram, peer).await {
+                info!("UDP echo to {} failed: {}", peer, err);
+            }
+        }
+    })
+}
@@ -0,0 +1,245 @@
+use crate::get_hostname;
+use log::info;
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{
+    DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    MethodDescriptorProto, ServiceDescriptorProto,
+};
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::net;
+use std::task::{Context, Poll};
+use tonic::body::Body;
+use tonic::codegen::{http, BoxFuture, Service, StdError};
+use tonic::metadata::KeyAndValueRef;
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+use tonic_health::pb::health_server::HealthServer;
+use tonic_health::server::{HealthReporter, HealthService};
+use tonic_prost::ProstCodec;
+
+pub use tonic_health::ServingStatus;
+
+const PACKAGE: &str = "rustwester.echo.v1";
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EchoRequest {
+    #[prost(string, tag = "1")]
+    pub message: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub payload: Vec<u8>,
+}
+
+/// A metadata entry of the request, binary values being base64 encoded as
+/// they are on the wire
+#[derive(Clone, PartialEq, Message)]
+pub struct MetadataEntry {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EchoResponse {
+    #[prost(string, tag = "1")]
+    pub message: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub payload: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub hostname: String,
+    #[prost(message, repeated, tag = "4")]
+    pub metadata: Vec<MetadataEntry>,
+    #[prost(string, tag = "5")]
+    pub peer: String,
+}
+
+/// The `rustwester.echo.v1.Echo` service, answering a request with what it
+/// carried and the host that served it
+#[derive(Clone, Default)]
+pub struct EchoServer;
+
+impl NamedService for EchoServer {
+    const NAME: &'static str = "rustwester.echo.v1.Echo";
+}
+
+impl UnaryService<EchoRequest> for EchoServer {
+    type Response = EchoResponse;
+    type Future = BoxFuture<Response<EchoResponse>, Status>;
+
+    fn call(&mut self, request: Request<EchoRequest>) -> Self::Future {
+        let peer = request
+            .remote_addr()
+            .map_or(String::new(), |addr| addr.to_string());
+        let metadata = request
+            .metadata()
+            .iter()
+            .map(|entry| match entry {
+                KeyAndValueRef::Ascii(key, value) => MetadataEntry {
+                    key: key.to_string(),
+                    value: value.to_str().unwrap_or_default().to_string(),
+                },
+                KeyAndValueRef::Binary(key, value) => MetadataEntry {
+                    key: key.to_string(),
+                    value: String::from_utf8_lossy(value.as_encoded_bytes()).to_string(),
+                },
+            })
+            .collect();
+        let request = request.into_inner();
+
+        Box::pin(async move {
+            Ok(Response::new(EchoResponse {
+                message: request.message,
+                payload: request.payload,
+                hostname: get_hostname().await,
+                metadata,
+                peer,
+            }))
+        })
+    }
+}
+
+impl<B> Service<http::Request<B>> for EchoServer
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let echo = self.clone();
+        match req.uri().path() {
+            "/rustwester.echo.v1.Echo/Echo" => {
+                Box::pin(async move { Ok(Grpc::new(ProstCodec::default()).unary(echo, req).await) })
+            }
+            _ => Box::pin(async move { Ok(Status::unimplemented("").into_http()) }),
+        }
+    }
+}
+
+fn field(name: &str, number: i32, kind: Type, label: Label) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+        name: Some(name.to_string()),
+        json_name: Some(name.to_string()),
+        number: Some(number),
+        label: Some(label as i32),
+        r#type: Some(kind as i32),
+        ..Default::default()
+    }
+}
+
+fn message(name: &str, field: Vec<FieldDescriptorProto>) -> DescriptorProto {
+    DescriptorProto {
+        name: Some(name.to_string()),
+        field,
+        ..Default::default()
+    }
+}
+
+/// Descriptor of the echo service for server reflection, written out as
+/// there's no `.proto` file to compile
+fn echo_descriptor() -> FileDescriptorSet {
+    let metadata = FieldDescriptorProto {
+        type_name: Some(format!(".{}.MetadataEntry", PACKAGE)),
+        ..field("metadata", 4, Type::Message, Label::Repeated)
+    };
+    FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("rustwester/echo/v1/echo.proto".to_string()),
+            package: Some(PACKAGE.to_string()),
+            message_type: vec![
+                message(
+                    "EchoRequest",
+                    vec![
+                        field("message", 1, Type::String, Label::Optional),
+                        field("payload", 2, Type::Bytes, Label::Optional),
+                    ],
+                ),
+                message(
+                    "MetadataEntry",
+                    vec![
+                        field("key", 1, Type::String, Label::Optional),
+                        field("value", 2, Type::String, Label::Optional),
+                    ],
+                ),
+                message(
+                    "EchoResponse",
+                    vec![
+                        field("message", 1, Type::String, Label::Optional),
+                        field("payload", 2, Type::Bytes, Label::Optional),
+                        field("hostname", 3, Type::String, Label::Optional),
+                        metadata,
+                        field("peer", 5, Type::String, Label::Optional),
+                    ],
+                ),
+            ],
+            service: vec![ServiceDescriptorProto {
+                name: Some("Echo".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Echo".to_string()),
+                    input_type: Some(format!(".{}.EchoRequest", PACKAGE)),
+                    output_type: Some(format!(".{}.EchoResponse", PACKAGE)),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            syntax: Some("proto3".to_string()),
+            ..Default::default()
+        }],
+    }
+}
+
+/// Parse a health status given to the admin routes
+pub fn parse_serving_status(status: &str) -> Option<ServingStatus> {
+    match status.to_ascii_lowercase().replace('-', "_").as_str() {
+        "serving" => Some(ServingStatus::Serving),
+        "not_serving" => Some(ServingStatus::NotServing),
+        "unknown" => Some(ServingStatus::Unknown),
+        _ => None,
+    }
+}
+
+/// Serve the health, echo and reflection services on `lst`, the health of
+/// the server and of the echo service being reported by `health`
+pub fn serve(
+    lst: net::TcpListener,
+    health: HealthReporter,
+) -> io::Result<impl Future<Output = io::Result<()>>> {
+    lst.set_nonblocking(true)?;
+    let incoming = TcpIncoming::from(tokio::net::TcpListener::from_std(lst)?);
+    info!("Serving gRPC on {}", incoming.local_addr()?);
+
+    let health_service = HealthServer::new(HealthService::from_health_reporter(health.clone()));
+    let reflection = || {
+        tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+            .register_file_descriptor_set(echo_descriptor())
+    };
+    let reflection_v1 = reflection().build_v1().map_err(io::Error::other)?;
+    let reflection_v1alpha = reflection().build_v1alpha().map_err(io::Error::other)?;
+
+    let router = tonic::transport::Server::builder()
+        .add_service(health_service)
+        .add_service(reflection_v1)
+        .add_service(reflection_v1alpha)
+        .add_service(EchoServer);
+
+    Ok(async move {
+        health
+            .set_service_status(EchoServer::NAME, ServingStatus::Serving)
+            .await;
+        router
+            .serve_with_incoming(incoming)
+            .await
+            .map_err(io::Error::other)
+    })
+}
@@ -0,0 +1,376 @@
+use super::stream::Peekable;
+use actix_http::Request;
+use actix_web::web::{BufMut, Bytes, BytesMut};
+use actix_web::HttpMessage;
+use log::debug;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Start of the HTTP/2 connection preface, enough to tell it from HTTP/1
+const PREFACE_START: &[u8] = b"PRI * HTTP/2";
+
+/// Length of the whole connection preface, `PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n`
+const PREFACE_LEN: usize = 24;
+
+/// Largest HTTP/1.1 head looked at for an `Upgrade: h2c`
+const MAX_UPGRADE_HEAD: usize = 16 * 1024;
+
+/// Largest body of an upgraded request, the initial flow control window
+const MAX_UPGRADE_BODY: usize = 65_535;
+
+/// Largest frame payload before the client's settings are known
+const MAX_FRAME_SIZE: usize = 16_384;
+
+/// Time a client has to send the request an upgrade is decided on
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const FRAME_DATA: u8 = 0x0;
+const FRAME_HEADERS: u8 = 0x1;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+/// Headers of HTTP/1.1 that have no place in HTTP/2
+const CONNECTION_HEADERS: [&str; 8] = [
+    "connection",
+    "host",
+    "http2-settings",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How an h2c connection started, and the IDs of the streams its client
+/// opened that are yet to reach the app
+pub struct H2cConnection {
+    pub negotiation: &'static str,
+    pub streams: Rc<RefCell<VecDeque<u32>>>,
+}
+
+/// Stream a request of an h2c or HTTP/3 connection arrived on
+pub struct StreamId {
+    pub id: u64,
+    /// Given by the protocol layer rather than inferred from the frames read
+    pub exact: bool,
+}
+
+/// Follows the frames read from an h2c connection, to learn the IDs of the
+/// streams the client opens, and slips the frames of an upgraded request in
+/// after the client's first `SETTINGS` frame
+pub struct H2cReader {
+    /// Bytes of the preface or of a frame payload left to pass
+    skip: usize,
+    header: [u8; 9],
+    header_len: usize,
+    frames: u64,
+    last_stream: u32,
+    streams: Rc<RefCell<VecDeque<u32>>>,
+    upgraded: Option<Bytes>,
+    negotiation: &'static str,
+}
+
+impl H2cReader {
+    fn new(negotiation: &'static str, upgraded: Option<Bytes>) -> Self {
+        Self {
+            skip: PREFACE_LEN,
+            header: [0; 9],
+            header_len: 0,
+            frames: 0,
+            last_stream: 0,
+            streams: Rc::default(),
+            upgraded,
+            negotiation,
+        }
+    }
+
+    pub fn connection(&self) -> H2cConnection {
+        H2cConnection {
+            negotiation: self.negotiation,
+            streams: self.streams.clone(),
+        }
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.skip > 0 {
+                let n = self.skip.min(data.len());
+                self.skip -= n;
+                data = &data[n..];
+                continue;
+            }
+
+            let n = (9 - self.header_len).min(data.len());
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&data[..n]);
+            self.header_len += n;
+            data = &data[n..];
+            if self.header_len == 9 {
+                let header = self.header;
+                let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+                let stream =
+                    u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+                // Trailers come in HEADERS frames too, but on known streams
+                if header[3] == FRAME_HEADERS && stream > self.last_stream {
+                    self.last_stream = stream;
+                    self.streams.borrow_mut().push_back(stream);
+                }
+                self.skip = len;
+                self.header_len = 0;
+                self.frames += 1;
+            }
+        }
+    }
+
+    pub fn poll_read<T: AsyncRead>(
+        &mut self,
+        io: Pin<&mut T>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let at_frame_boundary = self.skip == 0 && self.header_len == 0;
+        if let Some(upgraded) = self.upgraded.as_mut() {
+            if self.frames > 0 && at_frame_boundary {
+                let chunk = upgraded.split_to(upgraded.len().min(buf.remaining()));
+                if upgraded.is_empty() {
+                    self.upgraded = None;
+                }
+                buf.put_slice(&chunk);
+                self.feed(&chunk);
+                return Poll::Ready(Ok(()));
+            }
+
+            // Stop reading at the end of the first frame, the client's SETTINGS
+            let limit = if self.skip > 0 {
+                self.skip
+            } else {
+                9 - self.header_len
+            };
+            let mut tmp = vec![0; limit.min(buf.remaining())];
+            let mut limited = ReadBuf::new(&mut tmp);
+            let poll = io.poll_read(cx, &mut limited);
+            if let Poll::Ready(Ok(())) = poll {
+                let read = limited.filled();
+                buf.put_slice(read);
+                self.feed(read);
+            }
+            return poll;
+        }
+
+        let filled = buf.filled().len();
+        let poll = io.poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.feed(&buf.filled()[filled..]);
+        }
+        poll
+    }
+}
+
+/// Append an HPACK string literal, without Huffman coding
+fn hpack_string(out: &mut BytesMut, value: &[u8]) {
+    let mut len = value.len();
+    if len < 127 {
+        out.put_u8(len as u8);
+    } else {
+        out.put_u8(127);
+        len -= 127;
+        while len >= 128 {
+            out.put_u8((len % 128) as u8 | 0x80);
+            len /= 128;
+        }
+        out.put_u8(len as u8);
+    }
+    out.put_slice(value);
+}
+
+fn frame(out: &mut BytesMut, kind: u8, flags: u8, payload: &[u8]) {
+    out.put_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    out.put_u8(kind);
+    out.put_u8(flags);
+    out.put_u32(1);
+    out.put_slice(payload);
+}
+
+/// The HTTP/1.1 request a client asked to upgrade, as the HEADERS and DATA
+/// frames of stream 1, or `None` when it can't be upgraded
+fn upgrade_frames(req: &httparse::Request, body: &[u8]) -> Option<Bytes> {
+    let header = |name: &'static str| {
+        req.headers
+            .iter()
+            .filter(move |h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| String::from_utf8_lossy(h.value).to_ascii_lowercase())
+    };
+    let has_token = |name: &'static str, token: &str| {
+        header(name).any(|value| value.split(',').any(|t| t.trim() == token))
+    };
+    if req.version != Some(1)
+        || !has_token("upgrade", "h2c")
+        || !has_token("connection", "upgrade")
+        || header("http2-settings").count() != 1
+        || header("transfer-encoding").count() > 0
+    {
+        return None;
+    }
+
+    // Literal header fields without indexing, so no HPACK state is needed
+    let mut block = BytesMut::new();
+    let mut field = |name: &[u8], value: &[u8]| {
+        block.put_u8(0);
+        hpack_string(&mut block, name);
+        hpack_string(&mut block, value);
+    };
+    field(b":method", req.method?.as_bytes());
+    field(b":scheme", b"http");
+    field(b":path", req.path?.as_bytes());
+    if let Some(host) = req
+        .headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("host"))
+    {
+        field(b":authority", host.value);
+    }
+    for h in req.headers.iter() {
+        let name = h.name.to_ascii_lowercase();
+        if !CONNECTION_HEADERS.contains(&name.as_str()) {
+            field(name.as_bytes(), h.value);
+        }
+    }
+    if block.len() > MAX_FRAME_SIZE {
+        return None;
+    }
+
+    let mut frames = BytesMut::new();
+    let end_stream = if body.is_empty() { FLAG_END_STREAM } else { 0 };
+    frame(
+        &mut frames,
+        FRAME_HEADERS,
+        FLAG_END_HEADERS | end_stream,
+        &block,
+    );
+    let chunks = body.chunks(MAX_FRAME_SIZE).count();
+    for (i, chunk) in body.chunks(MAX_FRAME_SIZE).enumerate() {
+        let flags = if i + 1 == chunks { FLAG_END_STREAM } else { 0 };
+        frame(&mut frames, FRAME_DATA, flags, chunk);
+    }
+
+    Some(frames.freeze())
+}
+
+/// Head and body lengths of the request the buffer starts with, if it asks
+/// for an upgrade that can be made. `Err` means more bytes are needed
+fn upgrade_request(buf: &[u8]) -> Result<Option<(usize, usize)>, ()> {
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut req = httparse::Request::new(&mut headers);
+    let head_len = match req.parse(buf) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) if buf.len() < MAX_UPGRADE_HEAD => return Err(()),
+        _ => return Ok(None),
+    };
+    let body_len = req
+        .headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("content-length"))
+        .map(|h| {
+            std::str::from_utf8(h.value)
+                .ok()?
+                .trim()
+                .parse::<usize>()
+                .ok()
+        })
+        .unwrap_or(Some(0));
+
+    match body_len {
+        Some(body_len) if body_len <= MAX_UPGRADE_BODY => {
+            Ok(upgrade_frames(&req, &[]).map(|_| (head_len, body_len)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// How a connection starts
+enum Start {
+    PriorKnowledge,
+    /// Lengths of the head and of the body of an upgrade request
+    Upgrade(usize, usize),
+    Http1,
+}
+
+/// Tell HTTP/2 with prior knowledge from HTTP/1, and upgrade the HTTP/1.1
+/// connections asking for `Upgrade: h2c`. Returns the reader of the h2c
+/// connections, `None` leaving the connection to HTTP/1
+pub async fn negotiate<T: AsyncRead + AsyncWrite + Unpin>(
+    io: &mut Peekable<T>,
+) -> io::Result<Option<H2cReader>> {
+    let peeked = actix_web::rt::time::timeout(UPGRADE_TIMEOUT, async {
+        loop {
+            let buf = io.buffered();
+            if buf.starts_with(PREFACE_START) {
+                return Ok::<_, io::Error>(Start::PriorKnowledge);
+            }
+            if !PREFACE_START.starts_with(buf) {
+                match upgrade_request(buf) {
+                    Ok(Some((head_len, body_len))) => {
+                        return Ok(Start::Upgrade(head_len, body_len))
+                    }
+                    Ok(None) => return Ok(Start::Http1),
+                    Err(()) => {}
+                }
+            }
+            // The end of the stream
+            if io.fill(MAX_UPGRADE_HEAD).await? == 0 {
+                return Ok(Start::Http1);
+            }
+        }
+    })
+    .await;
+
+    // Slow clients are left to HTTP/1 and its own timeouts
+    let (head_len, body_len) = match peeked {
+        Ok(Ok(Start::PriorKnowledge)) => return Ok(Some(H2cReader::new("prior-knowledge", None))),
+        Ok(Ok(Start::Upgrade(head_len, body_len))) => (head_len, body_len),
+        Ok(Ok(Start::Http1)) | Err(_) => return Ok(None),
+        Ok(Err(err)) => return Err(err),
+    };
+
+    let mut request = vec![0; head_len + body_len];
+    actix_web::rt::time::timeout(UPGRADE_TIMEOUT, io.read_exact(&mut request))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "h2c upgrade body timed out"))??;
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut req = httparse::Request::new(&mut headers);
+    req.parse(&request)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let frames = upgrade_frames(&req, &request[head_len..])
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "h2c upgrade request"))?;
+    debug!("Upgrading {:?} {:?} to h2c", req.method, req.path);
+
+    io.write_all(
+        b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n",
+    )
+    .await?;
+    Ok(Some(H2cReader::new("upgrade", Some(frames))))
+}
+
+/// Give a request of an h2c connection the ID of its stream, best-effort.
+/// actix-http keeps the h2 stream to itself, so requests are matched in order
+/// with the streams seen opening, which goes wrong once streams are refused
+/// or reset before reaching the app
+pub fn tag_stream(req: &mut Request) {
+    if req.version() != actix_web::http::Version::HTTP_2 {
+        return;
+    }
+    let stream = req
+        .conn_data::<H2cConnection>()
+        .and_then(|conn| conn.streams.borrow_mut().pop_front());
+    if let Some(stream) = stream {
+        req.extensions_mut().insert(StreamId {
+            id: stream.into(),
+            exact: false,
+        });
+    }
+}
@@ -0,0 +1,257 @@
+use crate::routes::connection::Connection;
+use crate::routes::proxy_protocol::ProxyProtocolMode;
+use crate::server::h2c::StreamId;
+use crate::server::listener::ListenerInfo;
+use actix_http::{Payload, Request, Response};
+use actix_service::IntoServiceFactory;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{AppConfig, Service, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::{Method, Uri, Version};
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes};
+use actix_web::{Error, HttpMessage};
+use bytes::Buf;
+use futures_util::stream;
+use h3::server::RequestStream;
+use log::{debug, info};
+use quinn::crypto::rustls::QuicServerConfig;
+use rustls::ServerConfig;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Value of the `Alt-Svc` header advertising the QUIC listener
+#[derive(Debug, Clone)]
+pub struct AltSvc(pub String);
+
+impl AltSvc {
+    pub fn new(port: u16) -> Self {
+        Self(format!("h3=\":{}\"; ma=86400", port))
+    }
+}
+
+/// Advertise the QUIC listener on the responses of the TCP listener
+pub async fn alt_svc(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let alt_svc = match req.version() {
+        Version::HTTP_3 => None,
+        _ => req.app_data::<web::Data<AltSvc>>().cloned(),
+    };
+    let mut res = next.call(req).await?;
+    if let Some(alt_svc) = alt_svc {
+        if let Ok(value) = HeaderValue::from_str(&alt_svc.0) {
+            res.headers_mut().insert(header::ALT_SVC, value);
+        }
+    }
+
+    Ok(res)
+}
+
+type H3Connection = h3::server::Connection<h3_quinn::Connection, Bytes>;
+type SendStream = RequestStream<h3_quinn::SendStream<Bytes>, Bytes>;
+type RecvStream = RequestStream<h3_quinn::RecvStream, Bytes>;
+
+/// Bind a QUIC endpoint on `addr` and return its address with the future
+/// serving the app on it. Actix has no QUIC transport, so requests are
+/// converted from and to `h3` ones around a single instance of the app,
+/// which the future must be polled on a `LocalSet` to run
+pub fn serve<F, I, S, B>(
+    factory: F,
+    addr: SocketAddr,
+    tls: &ServerConfig,
+) -> io::Result<(SocketAddr, impl Future<Output = io::Result<()>>)>
+where
+    F: Fn() -> I + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<Error> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    let tls = super::tls::with_alpn(tls, &[b"h3"]);
+    let crypto = QuicServerConfig::try_from(tls)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let endpoint =
+        quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(crypto)), addr)?;
+    let local_addr = endpoint.local_addr()?;
+
+    Ok((local_addr, async move {
+        // As in `http_service`, with actix-web pinned for it
+        let service = factory()
+            .into_factory()
+            .new_service(AppConfig::__priv_test_new(
+                true,
+                local_addr.to_string(),
+                local_addr,
+            ))
+            .await
+            .map_err(|err| io::Error::other(format!("Cannot start the app: {:?}", err)))?;
+        let service = Rc::new(service);
+        let info = Arc::new(ListenerInfo {
+            name: local_addr.to_string(),
+            transport: "quic",
+            tls: true,
+            proxy_protocol: ProxyProtocolMode::Off,
+            h2c: false,
+        });
+        info!("Listening for HTTP/3 on {}", local_addr);
+
+        while let Some(incoming) = endpoint.accept().await {
+            let service = service.clone();
+            let info = info.clone();
+            actix_web::rt::spawn(async move {
+                let conn = match incoming.await {
+                    Ok(conn) => conn,
+                    Err(err) => return debug!("QUIC handshake failed: {}", err),
+                };
+                let peer = conn.remote_address();
+                debug!("QUIC connection from {}", peer);
+                match h3::server::Connection::new(h3_quinn::Connection::new(conn)).await {
+                    Ok(h3) => {
+                        accept(h3, service, peer, Connection::new(Some(local_addr), info)).await
+                    }
+                    Err(err) => debug!("HTTP/3 connection from {} failed: {}", peer, err),
+                }
+            });
+        }
+
+        Ok(())
+    }))
+}
+
+/// Serve the requests of an HTTP/3 connection until the client closes it
+async fn accept<S, B>(mut h3: H3Connection, service: Rc<S>, peer: SocketAddr, conn: Connection)
+where
+    S: Service<Request> + 'static,
+    S::Error: Into<Error>,
+    S::Response: Into<Response<B>>,
+    B: MessageBody + 'static,
+{
+    let conn = Rc::new(conn);
+    loop {
+        let resolver = match h3.accept().await {
+            Ok(Some(resolver)) => resolver,
+            Ok(None) => break,
+            Err(err) => {
+                debug!("HTTP/3 connection from {} closed: {}", peer, err);
+                break;
+            }
+        };
+        let service = service.clone();
+        let conn = conn.clone();
+        actix_web::rt::spawn(async move {
+            let (req, stream) = match resolver.resolve_request().await {
+                Ok(req) => req,
+                Err(err) => return debug!("Invalid HTTP/3 request from {}: {}", peer, err),
+            };
+            let stream_id = stream.id().into_inner();
+            let (send, recv) = stream.split();
+            let Some(mut req) = request(req, recv) else {
+                return debug!("Unsupported HTTP/3 request from {}", peer);
+            };
+            req.head_mut().peer_addr = Some(peer);
+            req.extensions_mut().insert(StreamId {
+                id: stream_id,
+                exact: true,
+            });
+            req.extensions_mut().insert(conn);
+            let head = req.method() == Method::HEAD;
+
+            let res = match service.call(req).await {
+                Ok(res) => res.into().map_into_boxed_body(),
+                Err(err) => err.into().error_response().into(),
+            };
+            if let Err(err) = respond(send, res, head).await {
+                debug!("Cannot answer HTTP/3 request from {}: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Actix request for an `h3` one, its body read from `recv` as the app
+/// consumes it
+fn request(req: http::Request<()>, recv: RecvStream) -> Option<Request> {
+    let payload = stream::unfold(recv, |mut recv| async move {
+        match recv.recv_data().await {
+            Ok(Some(mut data)) => Some((Ok(data.copy_to_bytes(data.remaining())), recv)),
+            Ok(None) => None,
+            Err(err) => Some((Err(PayloadError::Io(io::Error::other(err))), recv)),
+        }
+    });
+    let mut actix = Request::with_payload(Payload::from(
+        Box::pin(payload) as actix_http::BoxedPayloadStream
+    ));
+
+    let head = actix.head_mut();
+    head.method = Method::from_bytes(req.method().as_str().as_bytes()).ok()?;
+    head.uri = Uri::try_from(req.uri().to_string()).ok()?;
+    head.version = Version::HTTP_3;
+    for (name, value) in req.headers() {
+        head.headers.append(
+            HeaderName::from_bytes(name.as_str().as_bytes()).ok()?,
+            HeaderValue::from_bytes(value.as_bytes()).ok()?,
+        );
+    }
+    // HTTP/3 moves `Host` to the `:authority` pseudo-header
+    if !head.headers.contains_key(header::HOST) {
+        if let Some(authority) = req.uri().authority() {
+            head.headers.insert(
+                header::HOST,
+                HeaderValue::from_str(authority.as_str()).ok()?,
+            );
+        }
+    }
+
+    Some(actix)
+}
+
+/// Send the head of an actix response, then its body unless the request
+/// was a `HEAD` one
+async fn respond(
+    mut send: SendStream,
+    res: Response<actix_web::body::BoxBody>,
+    head: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (res, body) = res.into_parts();
+    let mut builder = http::Response::builder().status(res.status().as_u16());
+    for (name, value) in res.headers() {
+        // Connection-specific headers are forbidden in HTTP/3
+        if matches!(
+            *name,
+            header::CONNECTION | header::TRANSFER_ENCODING | header::UPGRADE
+        ) || name.as_str() == "keep-alive"
+        {
+            continue;
+        }
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    if let BodySize::Sized(size) = body.size() {
+        if !res.headers().contains_key(header::CONTENT_LENGTH) {
+            builder = builder.header("content-length", size);
+        }
+    }
+    send.send_response(builder.body(())?).await?;
+
+    if !head {
+        let mut body = pin!(body);
+        while let Some(chunk) = poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+            let chunk = chunk.map_err(|err| err.to_string())?;
+            if !chunk.is_empty() {
+                send.send_data(chunk).await?;
+            }
+        }
+    }
+    send.finish().await?;
+
+    Ok(())
+}
@@ -0,0 +1,297 @@
+use super::ListenerOptions;
+use crate::routes::proxy_protocol::ProxyProtocolMode;
+use clap::ValueEnum;
+use log::warn;
+use serde_json::{json, Value};
+use socket2::{Domain, SockAddr, Socket as RawSocket, Type};
+use std::fmt;
+use std::io;
+use std::net::{self, SocketAddr, ToSocketAddrs};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+
+/// Connections waiting to be accepted by a listener, by default
+pub const BACKLOG: u32 = 1024;
+
+/// Where a listener accepts connections
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenAddress {
+    /// `HOST:PORT`, IPv6 addresses in brackets
+    Tcp(String),
+    Unix(PathBuf),
+    /// Sockets of that name passed by systemd
+    Fd(String),
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(addr) => f.write_str(addr),
+            ListenAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+            ListenAddress::Fd(name) => write!(f, "fd:{}", name),
+        }
+    }
+}
+
+/// A `--listen` address and the options overriding the global ones for it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListenSpec {
+    pub address: ListenAddress,
+    pub tls: Option<bool>,
+    pub h2c: Option<bool>,
+    pub proxy_protocol: Option<ProxyProtocolMode>,
+}
+
+impl ListenSpec {
+    pub fn new(address: ListenAddress) -> Self {
+        Self {
+            address,
+            tls: None,
+            h2c: None,
+            proxy_protocol: None,
+        }
+    }
+}
+
+/// Parse a listener, e.g. `[::]:9443,tls`,
+/// `unix:/run/wester.sock,proxy-protocol=required` or `fd:web,h2c`
+pub fn parse_listen(value: &str) -> Result<ListenSpec, String> {
+    let mut parts = value.split(',');
+    let address = parts.next().unwrap_or_default();
+    let address = match (address.strip_prefix("unix:"), address.strip_prefix("fd:")) {
+        (Some(""), _) => return Err("Missing Unix socket path".to_string()),
+        (Some(path), _) => ListenAddress::Unix(PathBuf::from(path)),
+        (_, Some("")) => return Err("Missing systemd socket name".to_string()),
+        (_, Some(name)) => ListenAddress::Fd(name.to_string()),
+        _ if address.rsplit_once(':').is_some() => ListenAddress::Tcp(address.to_string()),
+        _ => {
+            return Err(format!(
+                "Expected HOST:PORT, unix:PATH or fd:NAME, got {}",
+                address
+            ))
+        }
+    };
+
+    let mut spec = ListenSpec::new(address);
+    for option in parts {
+        match option.split_once('=') {
+            None if option == "tls" => spec.tls = Some(true),
+            None if option == "no-tls" => spec.tls = Some(false),
+            None if option == "h2c" => spec.h2c = Some(true),
+            None if option == "no-h2c" => spec.h2c = Some(false),
+            Some(("proxy-protocol", mode)) => {
+                spec.proxy_protocol = Some(ProxyProtocolMode::from_str(mode, true)?)
+            }
+            _ => return Err(format!("Unknown listener option {}", option)),
+        }
+    }
+    Ok(spec)
+}
+
+/// Socket a listener accepts connections on
+pub enum Socket {
+    Tcp(net::TcpListener),
+    Unix(UnixListener),
+}
+
+/// What responses report of the listener a connection was accepted on
+#[derive(Clone, Debug)]
+pub struct ListenerInfo {
+    pub name: String,
+    pub transport: &'static str,
+    pub tls: bool,
+    pub proxy_protocol: ProxyProtocolMode,
+    pub h2c: bool,
+}
+
+impl ListenerInfo {
+    pub fn report(&self) -> Value {
+        json!({
+            "name": self.name,
+            "transport": self.transport,
+            "tls": self.tls,
+            "proxy_protocol": format!("{:?}", self.proxy_protocol).to_lowercase(),
+            "h2c": self.h2c,
+        })
+    }
+}
+
+/// A bound listener of the service
+pub struct Listener {
+    pub name: String,
+    pub socket: Socket,
+    pub options: ListenerOptions,
+}
+
+impl Listener {
+    pub fn tcp(lst: net::TcpListener, options: ListenerOptions) -> io::Result<Self> {
+        Ok(Self {
+            name: lst.local_addr()?.to_string(),
+            socket: Socket::Tcp(lst),
+            options,
+        })
+    }
+
+    pub fn unix(lst: UnixListener, options: ListenerOptions) -> io::Result<Self> {
+        let name = match lst.local_addr()?.as_pathname() {
+            Some(path) => format!("unix:{}", path.display()),
+            None => "unix".to_string(),
+        };
+        Ok(Self {
+            name,
+            socket: Socket::Unix(lst),
+            options,
+        })
+    }
+
+    fn new(socket: Socket, options: ListenerOptions) -> io::Result<Self> {
+        match socket {
+            Socket::Tcp(lst) => Self::tcp(lst, options),
+            Socket::Unix(lst) => Self::unix(lst, options),
+        }
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.socket {
+            Socket::Tcp(lst) => lst.local_addr().ok(),
+            Socket::Unix(_) => None,
+        }
+    }
+
+    pub fn info(&self) -> ListenerInfo {
+        ListenerInfo {
+            name: self.name.clone(),
+            transport: match self.socket {
+                Socket::Tcp(_) => "tcp",
+                Socket::Unix(_) => "unix",
+            },
+            tls: self.options.tls.is_some(),
+            proxy_protocol: self.options.proxy_protocol,
+            h2c: self.options.h2c,
+        }
+    }
+}
+
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} resolves to no address", addr),
+        )
+    })
+}
+
+fn bind_tcp(addr: SocketAddr, ipv6_only: bool, backlog: i32) -> io::Result<net::TcpListener> {
+    let socket = RawSocket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(backlog)?;
+    Ok(socket.into())
+}
+
+fn bind_unix(path: &PathBuf, backlog: i32) -> io::Result<UnixListener> {
+    // A socket left behind by a previous run would fail the bind
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let socket = RawSocket::new(Domain::UNIX, Type::STREAM, None)?;
+    socket.bind(&SockAddr::unix(path)?)?;
+    socket.listen(backlog)?;
+    Ok(UnixListener::from(OwnedFd::from(socket)))
+}
+
+/// A listen address resolved for binding
+enum Target<'a> {
+    Tcp(SocketAddr),
+    Unix(&'a PathBuf),
+    Bound(Vec<Socket>),
+}
+
+/// Bind every listener, the options they don't set being taken from
+/// `defaults`. IPv6 wildcard listeners are dual-stack, unless an IPv4 one
+/// shares their port. `fd:NAME` listeners take the sockets of that name from
+/// `inherited`, the ones left unused being closed, and keep their backlog
+pub fn bind(
+    specs: &[ListenSpec],
+    defaults: &ListenerOptions,
+    backlog: u32,
+    mut inherited: Vec<(String, Socket)>,
+) -> io::Result<Vec<Listener>> {
+    let targets = specs
+        .iter()
+        .map(|spec| match &spec.address {
+            ListenAddress::Tcp(addr) => resolve(addr).map(Target::Tcp),
+            ListenAddress::Unix(path) => Ok(Target::Unix(path)),
+            ListenAddress::Fd(name) => {
+                let (sockets, others): (Vec<_>, Vec<_>) =
+                    inherited.drain(..).partition(|(n, _)| n == name);
+                inherited = others;
+                match sockets.is_empty() {
+                    true => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No socket {} was passed by systemd", name),
+                    )),
+                    false => Ok(Target::Bound(
+                        sockets.into_iter().map(|(_, socket)| socket).collect(),
+                    )),
+                }
+            }
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    for (name, _) in inherited {
+        warn!(
+            "Closing socket {} passed by systemd, no listener uses it",
+            name
+        );
+    }
+    let ipv4_ports: Vec<u16> = targets
+        .iter()
+        .filter_map(|target| match target {
+            Target::Tcp(addr) if addr.is_ipv4() => Some(addr.port()),
+            _ => None,
+        })
+        .collect();
+
+    let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+    let mut listeners = Vec::new();
+    for (spec, target) in specs.iter().zip(targets) {
+        let tls = match spec.tls {
+            Some(true) if defaults.tls.is_none() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Listener {} needs --tls-cert and --tls-key", spec.address),
+                ))
+            }
+            Some(false) => None,
+            _ => defaults.tls.clone(),
+        };
+        let options = ListenerOptions {
+            proxy_protocol: spec.proxy_protocol.unwrap_or(defaults.proxy_protocol),
+            h2c: spec.h2c.unwrap_or(defaults.h2c),
+            tls,
+        };
+
+        match target {
+            Target::Tcp(addr) => {
+                let ipv6_only = addr.ip().is_unspecified() && ipv4_ports.contains(&addr.port());
+                listeners.push(Listener::tcp(bind_tcp(addr, ipv6_only, backlog)?, options)?)
+            }
+            Target::Unix(path) => {
+                listeners.push(Listener::unix(bind_unix(path, backlog)?, options)?)
+            }
+            Target::Bound(sockets) => {
+                for socket in sockets {
+                    listeners.push(Listener::new(socket, options.clone())?);
+                }
+            }
+        }
+    }
+    Ok(listeners)
+}
@@ -0,0 +1,389 @@
+pub mod echo;
+pub mod grpc;
+pub mod h2c;
+pub mod h3;
+pub mod listener;
+pub mod reload;
+pub mod shutdown;
+pub mod stream;
+pub mod systemd;
+pub mod tls;
+
+use crate::routes::connection;
+use crate::routes::proxy_protocol::{read_header, ProxyConnection, ProxyProtocolMode};
+use actix_http::error::DispatchError;
+use actix_http::{HttpService, KeepAlive, Protocol, Request, Response};
+use actix_service::{
+    apply_fn_factory, fn_service, map_config, IntoServiceFactory, ServiceFactoryExt,
+};
+use actix_web::body::MessageBody;
+use actix_web::dev::{AppConfig, Extensions, Server, Service, ServiceFactory};
+use actix_web::Error;
+use h2c::H2cReader;
+use listener::{Listener, ListenerInfo, Socket};
+use log::debug;
+use rustls::ServerConfig;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use stream::{Peekable, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Time a client has to complete the TLS handshake
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What a listener reads before HTTP, and the HTTP versions it speaks
+#[derive(Clone, Default)]
+pub struct ListenerOptions {
+    pub proxy_protocol: ProxyProtocolMode,
+    pub h2c: bool,
+    /// TLS configuration, negotiating HTTP/2 or HTTP/1.1 with ALPN
+    pub tls: Option<Arc<ServerConfig>>,
+}
+
+impl fmt::Debug for ListenerOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListenerOptions")
+            .field("proxy_protocol", &self.proxy_protocol)
+            .field("h2c", &self.h2c)
+            .field("tls", &self.tls.is_some())
+            .finish()
+    }
+}
+
+/// How the HTTP server runs, whatever the listener. Zero timeouts are
+/// disabled
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    pub workers: usize,
+    /// Connections waiting to be accepted by each listener
+    pub backlog: u32,
+    /// Connections served at once by each worker
+    pub max_connections: usize,
+    /// Time an idle connection is kept open
+    pub keep_alive: Duration,
+    /// Time a client has to send the head of its first request
+    pub client_request_timeout: Duration,
+    /// Time a client has to acknowledge the end of the connection
+    pub client_disconnect_timeout: Duration,
+    /// Time requests in flight have to complete when stopping gracefully
+    pub shutdown_timeout: Duration,
+}
+
+/// The defaults of actix, but for a smaller backlog
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map_or(2, NonZeroUsize::get),
+            backlog: listener::BACKLOG,
+            max_connections: 25_000,
+            keep_alive: Duration::from_secs(5),
+            client_request_timeout: Duration::from_secs(5),
+            client_disconnect_timeout: Duration::ZERO,
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ServerOptions {
+    pub fn report(&self) -> Value {
+        json!({
+            "workers": self.workers,
+            "backlog": self.backlog,
+            "max_connections": self.max_connections,
+            "keep_alive_ms": self.keep_alive.as_millis(),
+            "client_request_timeout_ms": self.client_request_timeout.as_millis(),
+            "client_disconnect_timeout_ms": self.client_disconnect_timeout.as_millis(),
+            "shutdown_timeout_ms": self.shutdown_timeout_secs() * 1000,
+        })
+    }
+
+    /// The shutdown timeout as actix applies it, in whole seconds, rounded up
+    /// so a timeout is never dropped
+    pub fn shutdown_timeout_secs(&self) -> u64 {
+        self.shutdown_timeout.as_millis().div_ceil(1000) as u64
+    }
+}
+
+/// Parse a duration, e.g. `500ms`, `30s`, `5m` or `1h`, plain numbers being
+/// seconds
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration {}", duration))?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        unit => return Err(format!("Unknown duration unit {}", unit)),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Parse a duration as `parse_duration` does, for the settings applied in
+/// whole seconds
+pub fn parse_whole_seconds(duration: &str) -> Result<Duration, String> {
+    let parsed = parse_duration(duration)?;
+    match parsed.subsec_nanos() {
+        0 => Ok(parsed),
+        _ => Err(format!(
+            "Duration {} must be whole seconds",
+            duration.trim()
+        )),
+    }
+}
+
+enum Io {
+    Plain(Peekable<Stream>),
+    Tls(Box<TlsStream<Peekable<Stream>>>),
+}
+
+/// An accepted connection, with the PROXY header it started with and the
+/// state of its h2c negotiation
+pub struct Conn {
+    io: Io,
+    local_addr: Option<SocketAddr>,
+    proxy: Option<ProxyConnection>,
+    h2c: Option<H2cReader>,
+}
+
+impl AsyncRead for Conn {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match (&mut this.io, this.h2c.as_mut()) {
+            (Io::Plain(io), Some(h2c)) => h2c.poll_read(Pin::new(io), cx, buf),
+            (Io::Plain(io), None) => Pin::new(io).poll_read(cx, buf),
+            (Io::Tls(io), _) => Pin::new(io).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Conn {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().io {
+            Io::Plain(io) => Pin::new(io).poll_write(cx, buf),
+            Io::Tls(io) => Pin::new(io).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Plain(io) => Pin::new(io).poll_flush(cx),
+            Io::Tls(io) => Pin::new(io).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Plain(io) => Pin::new(io).poll_shutdown(cx),
+            Io::Tls(io) => Pin::new(io).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Read the PROXY header of a new connection, the source address it carries
+/// becoming the peer address of the requests, then either complete the TLS
+/// handshake or tell HTTP/1 from h2c
+async fn accept(
+    io: Stream,
+    options: ListenerOptions,
+) -> io::Result<(Conn, Protocol, Option<SocketAddr>)> {
+    let peer = io.peer_addr();
+    let local_addr = io.local_addr();
+    let mut io = Peekable::new(io);
+    let header = match options.proxy_protocol {
+        ProxyProtocolMode::Off => None,
+        mode => read_header(&mut io, mode)
+            .await
+            .inspect_err(|err| debug!("Dropping connection from {:?}: {}", peer, err))?,
+    };
+    let peer_addr = header.as_ref().and_then(|header| header.source).or(peer);
+    let proxy = header.map(|header| ProxyConnection { header, peer });
+
+    if let Some(tls) = options.tls {
+        let io =
+            actix_web::rt::time::timeout(TLS_HANDSHAKE_TIMEOUT, TlsAcceptor::from(tls).accept(io))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+                .inspect_err(|err| debug!("TLS handshake with {:?} failed: {}", peer, err))?;
+        let protocol = match io.get_ref().1.alpn_protocol() {
+            Some(b"h2") => Protocol::Http2,
+            _ => Protocol::Http1,
+        };
+        debug!("TLS connection from {:?}, {:?}", peer, protocol);
+        let conn = Conn {
+            io: Io::Tls(Box::new(io)),
+            local_addr,
+            proxy,
+            h2c: None,
+        };
+        return Ok((conn, protocol, peer_addr));
+    }
+
+    let h2c = match options.h2c {
+        true => h2c::negotiate(&mut io).await?,
+        false => None,
+    };
+    debug!(
+        "Connection from {:?}, PROXY {:?}, h2c {:?}",
+        peer,
+        proxy.as_ref().map(|proxy| &proxy.header),
+        h2c.as_ref().map(|h2c| h2c.connection().negotiation)
+    );
+    let protocol = match h2c {
+        Some(_) => Protocol::Http2,
+        None => Protocol::Http1,
+    };
+    let conn = Conn {
+        io: Io::Plain(io),
+        local_addr,
+        proxy,
+        h2c,
+    };
+
+    Ok((conn, protocol, peer_addr))
+}
+
+/// The service of a listener, from accepting a connection to calling the app
+fn http_service<F, I, S, B, T>(
+    factory: &F,
+    server_options: &ServerOptions,
+    options: ListenerOptions,
+    info: Arc<ListenerInfo>,
+    addr: SocketAddr,
+    stream: fn(T) -> Stream,
+) -> impl ServiceFactory<T, Config = (), Response = (), Error = DispatchError, InitError = ()>
+where
+    F: Fn() -> I,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<Error> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+    T: 'static,
+{
+    let secure = options.tls.is_some();
+    let app = apply_fn_factory(factory().into_factory(), |mut req, srv| {
+        h2c::tag_stream(&mut req);
+        srv.call(req)
+    })
+    .map_err(|err| err.into().error_response());
+    let keep_alive = match server_options.keep_alive.is_zero() {
+        true => KeepAlive::Disabled,
+        false => KeepAlive::Timeout(server_options.keep_alive),
+    };
+    let mut http = HttpService::build()
+        .local_addr(addr)
+        .keep_alive(keep_alive)
+        .client_request_timeout(server_options.client_request_timeout)
+        .client_disconnect_timeout(server_options.client_disconnect_timeout);
+    if secure {
+        http = http.secure();
+    }
+    let http = http
+        .on_connect_ext(move |io: &Conn, ext: &mut Extensions| {
+            connection::register(ext, io.local_addr, info.clone());
+            if let Some(proxy) = &io.proxy {
+                ext.insert(proxy.clone());
+            }
+            if let Some(h2c) = &io.h2c {
+                ext.insert(h2c.connection());
+            }
+        })
+        // `AppConfig::new` is private to actix-web, which is pinned for its
+        // doc-hidden stand-in. `secure` gives https to `connection_info`
+        .finish(map_config(app, move |_| {
+            AppConfig::__priv_test_new(secure, addr.to_string(), addr)
+        }));
+
+    fn_service(move |io: T| accept(stream(io), options.clone()))
+        .map_err(DispatchError::Io)
+        .and_then(http)
+}
+
+/// Serve the app on every listener. `HttpServer` has no hook before HTTP
+/// parsing, for PROXY headers and h2c upgrades, so the services are built the
+/// way it builds its own. Signals are left to [`shutdown::on_signal`]
+pub fn serve<F, I, S, B>(
+    factory: F,
+    listeners: Vec<Listener>,
+    server_options: &ServerOptions,
+) -> io::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<Error> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    let mut server = Server::build()
+        .workers(server_options.workers)
+        .max_concurrent_connections(server_options.max_connections)
+        .shutdown_timeout(server_options.shutdown_timeout_secs())
+        .disable_signals();
+    for listener in listeners {
+        let info = Arc::new(listener.info());
+        // Unix sockets have no address, requests without `Host` get this one
+        let addr = listener
+            .local_addr()
+            .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 0)));
+        let name = format!("rustwester-{}", listener.name);
+        let options = listener.options;
+        let factory = factory.clone();
+        let server_options = server_options.clone();
+        server = match listener.socket {
+            Socket::Tcp(lst) => server.listen(name, lst, move || {
+                let info = info.clone();
+                http_service(
+                    &factory,
+                    &server_options,
+                    options.clone(),
+                    info,
+                    addr,
+                    Stream::Tcp,
+                )
+            })?,
+            Socket::Unix(lst) => server.listen_uds(name, lst, move || {
+                let info = info.clone();
+                http_service(
+                    &factory,
+                    &server_options,
+                    options.clone(),
+                    info,
+                    addr,
+                    Stream::Unix,
+                )
+            })?,
+        };
+    }
+    Ok(server.run())
+}
@@ -0,0 +1,46 @@
+use log::info;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Interval at which a watched configuration file is checked for changes
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Call `reload` on `SIGHUP` and, when `watch` names a file, whenever it is
+/// modified
+pub fn on_reload(
+    watch: Option<PathBuf>,
+    reload: impl Fn() + Send + 'static,
+) -> io::Result<impl Future<Output = ()>> {
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    Ok(async move {
+        let mut last_modified = watch.as_deref().and_then(modified);
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => info!("SIGHUP received, reloading the configuration"),
+                _ = interval.tick(), if watch.is_some() => {
+                    let Some(path) = watch.as_deref() else {
+                        continue;
+                    };
+                    let current = modified(path);
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+                    info!("{} modified, reloading the configuration", path.display());
+                }
+            }
+            reload();
+        }
+    })
+}
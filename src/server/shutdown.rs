@@ -0,0 +1,71 @@
+use super::systemd;
+use crate::routes::lifecycle::Lifecycle;
+use actix_web::dev::ServerHandle;
+use actix_web::web;
+use futures_util::future::join_all;
+use log::info;
+use sd_notify::NotifyState;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Interval of the progress logged while draining
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When asked to stop by a signal, fail readiness, keep serving for `delay`
+/// then stop `servers`. `SIGTERM` stops gracefully, requests in flight having
+/// the shutdown timeout of the servers to complete, while `SIGINT` and
+/// `SIGQUIT` stop at once. Another signal cuts the delay short
+pub fn on_signal(
+    servers: Vec<ServerHandle>,
+    lifecycle: web::Data<Lifecycle>,
+    delay: Duration,
+) -> io::Result<impl Future<Output = ()>> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut quit = signal(SignalKind::quit())?;
+
+    Ok(async move {
+        let (name, graceful) = tokio::select! {
+            _ = terminate.recv() => ("SIGTERM", true),
+            _ = interrupt.recv() => ("SIGINT", false),
+            _ = quit.recv() => ("SIGQUIT", false),
+        };
+        info!(
+            "{} received with {} requests in flight, failing readiness",
+            name,
+            lifecycle.in_flight()
+        );
+        lifecycle.start_draining();
+        systemd::notify(&[NotifyState::Stopping]);
+
+        if graceful && !delay.is_zero() {
+            info!("Still serving for {:?} before draining", delay);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = terminate.recv() => info!("SIGTERM received again, draining now"),
+                _ = interrupt.recv() => info!("SIGINT received, draining now"),
+                _ = quit.recv() => info!("SIGQUIT received, draining now"),
+            }
+        }
+
+        match graceful {
+            true => info!("Draining {} requests in flight", lifecycle.in_flight()),
+            false => info!("Stopping with {} requests in flight", lifecycle.in_flight()),
+        }
+        let stopped = join_all(servers.iter().map(|server| server.stop(graceful)));
+        let progress = async {
+            let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                info!("Draining, {} requests in flight", lifecycle.in_flight());
+            }
+        };
+        tokio::select! {
+            _ = stopped => {}
+            _ = progress => {}
+        }
+    })
+}
@@ -0,0 +1,56 @@
+use super::listener::Socket;
+use log::{debug, info, warn};
+use sd_notify::NotifyState;
+use socket2::{Socket as RawSocket, Type};
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::net::UnixListener;
+
+/// Sockets passed by systemd with `LISTEN_FDS`, named after their
+/// `FileDescriptorName=`, or the socket unit when it has none. The variables
+/// are removed, so the processes spawned later don't take them for theirs
+pub fn listen_fds() -> io::Result<Vec<(String, Socket)>> {
+    // SAFETY: called once on startup, before any task that reads or writes
+    // the environment is spawned
+    unsafe { sd_notify::listen_fds_with_names_and_unset_env() }?
+        .map(|(fd, name)| {
+            // SAFETY: the descriptors passed by systemd belong to this
+            // process, and are only taken here
+            let socket = unsafe { RawSocket::from_raw_fd(fd) };
+            if socket.r#type()? != Type::STREAM {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Socket {} passed by systemd isn't a stream socket", name),
+                ));
+            }
+            info!("Using socket {} passed by systemd", name);
+            let socket = match socket.local_addr()?.is_unix() {
+                true => Socket::Unix(UnixListener::from(OwnedFd::from(socket))),
+                false => Socket::Tcp(socket.into()),
+            };
+            Ok((name, socket))
+        })
+        .collect()
+}
+
+/// Tell systemd about the state of the service, when it asked with
+/// `NOTIFY_SOCKET`
+pub fn notify(state: &[NotifyState]) {
+    if let Err(err) = sd_notify::notify(state) {
+        warn!("Notifying systemd failed: {}", err);
+    }
+}
+
+/// Ping the systemd watchdog at half its `WatchdogSec=`, as long as the
+/// runtime runs
+pub async fn watchdog() {
+    let Some(timeout) = sd_notify::watchdog_enabled() else {
+        return;
+    };
+    debug!("Pinging the systemd watchdog every {:?}", timeout / 2);
+    let mut interval = tokio::time::interval(timeout / 2);
+    loop {
+        interval.tick().await;
+        notify(&[NotifyState::Watchdog]);
+    }
+}
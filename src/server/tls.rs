@@ -0,0 +1,33 @@
+use crate::utils::structs::{Result, WesterError};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Server configuration from PEM files, shared by the TCP and QUIC
+/// listeners, which each set their ALPN protocols
+pub fn load(cert: &Path, key: &Path) -> Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|err| {
+            WesterError::Other(format!("Invalid certificate {}: {}", cert.display(), err))
+        })?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|err| {
+        WesterError::Other(format!("Invalid private key {}: {}", key.display(), err))
+    })?;
+
+    Ok(
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?,
+    )
+}
+
+/// A copy of `config` negotiating `protocols` with ALPN
+pub fn with_alpn(config: &ServerConfig, protocols: &[&[u8]]) -> Arc<ServerConfig> {
+    let mut config = config.clone();
+    config.alpn_protocols = protocols.iter().map(|p| p.to_vec()).collect();
+    Arc::new(config)
+}
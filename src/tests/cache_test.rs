@@ -0,0 +1,174 @@
+use super::super::*;
+use super::{get_alone, local_listener, spawn_service};
+use actix_web::{http, test, App};
+use server::ServerOptions;
+
+#[actix_web::test]
+async fn test_prepare_response_stable_etag() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/?json").to_request();
+    let first = test::call_service(&app, req).await;
+    let req = test::TestRequest::get().uri("/?json").to_request();
+    let second = test::call_service(&app, req).await;
+    let req = test::TestRequest::get().uri("/").to_request();
+    let html = test::call_service(&app, req).await;
+
+    let etag = first.headers().get(header::ETAG).unwrap();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+    assert_eq!(etag, second.headers().get(header::ETAG).unwrap());
+    assert_ne!(etag, html.headers().get(header::ETAG).unwrap());
+}
+
+#[actix_web::test]
+async fn test_cache_if_none_match() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(cache),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/cache").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(resp.headers().get(header::LAST_MODIFIED).is_some());
+    let etag = resp.headers().get(header::ETAG).unwrap().clone();
+
+    let req = test::TestRequest::get()
+        .uri("/cache")
+        .insert_header((header::IF_NONE_MATCH, etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.headers().get(header::ETAG).unwrap(), etag);
+    assert!(test::read_body(resp).await.is_empty());
+
+    let req = test::TestRequest::get()
+        .uri("/cache")
+        .insert_header((header::IF_NONE_MATCH, "\"something-else\""))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_cache_if_modified_since() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(cache),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/cache").to_request();
+    let resp = test::call_service(&app, req).await;
+    let last_modified = resp.headers().get(header::LAST_MODIFIED).unwrap().clone();
+
+    let req = test::TestRequest::get()
+        .uri("/cache")
+        .insert_header((header::IF_MODIFIED_SINCE, last_modified))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+
+    let req = test::TestRequest::get()
+        .uri("/cache")
+        .insert_header((header::IF_MODIFIED_SINCE, "Sat, 01 Jan 2000 00:00:00 GMT"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_cache_if_match() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(cache),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/cache")
+        .insert_header((header::IF_MATCH, "\"something-else\""))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::PRECONDITION_FAILED);
+
+    let req = test::TestRequest::get()
+        .uri("/cache")
+        .insert_header((header::IF_MATCH, "*"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_cache_max_age() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(cache_for),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/cache/60").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CACHE_CONTROL).unwrap(),
+        "public, max-age=60"
+    );
+}
+
+fn header_of<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    response
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+}
+
+#[actix_web::test]
+async fn test_cache_preconditions_through_middlewares() {
+    let (addr, listener) = local_listener(ListenerOptions::default());
+    let handle = spawn_service(
+        || {
+            let app = App::new().app_data(web::Data::new(AppState { allow_json: true }));
+            wrap_middlewares(app, true).service(cache)
+        },
+        vec![listener],
+        &ServerOptions::default(),
+    );
+
+    let response = get_alone(addr, "/cache?json", &[]).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    let etag = header_of(&response, "etag").unwrap().to_string();
+
+    let response = get_alone(addr, "/cache?json", &[("If-None-Match", &etag)]).await;
+    assert!(response.starts_with("HTTP/1.1 304"), "{}", response);
+    assert_eq!(header_of(&response, "etag"), Some(etag.as_str()));
+
+    // Weak, the client section being left out, so never matched by If-Match
+    assert!(etag.starts_with("W/"), "{}", etag);
+    let response = get_alone(addr, "/cache?json", &[("If-Match", &etag)]).await;
+    assert!(response.starts_with("HTTP/1.1 412"), "{}", response);
+    let response = get_alone(addr, "/cache?json", &[("If-Match", "*")]).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    let response = get_alone(addr, "/cache?json", &[("If-Match", "\"something-else\"")]).await;
+    assert!(response.starts_with("HTTP/1.1 412"), "{}", response);
+    handle.stop(false).await;
+}
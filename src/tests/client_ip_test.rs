@@ -0,0 +1,135 @@
+use super::super::*;
+use actix_web::{http, test, App};
+
+fn trusted(proxies: &[&str]) -> web::Data<TrustedProxies> {
+    web::Data::new(TrustedProxies(
+        proxies
+            .iter()
+            .map(|proxy| parse_trusted_proxy(proxy).unwrap())
+            .collect(),
+    ))
+}
+
+async fn client_report(proxies: &[&str], req: test::TestRequest) -> Value {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(trusted(proxies))
+            .service(ip),
+    )
+    .await;
+
+    let req = req.uri("/ip?json").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+
+    body["response"].clone()
+}
+
+#[actix_web::test]
+async fn test_client_ip_untrusted_peer_ignores_headers() {
+    let report = client_report(
+        &["10.0.0.0/8"],
+        test::TestRequest::get()
+            .peer_addr("198.51.100.7:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "1.2.3.4"))
+            .insert_header(("X-Real-IP", "1.2.3.4")),
+    )
+    .await;
+
+    assert_eq!(report["client_ip"], "198.51.100.7");
+    assert_eq!(report["source"], "peer");
+    assert_eq!(report["peer_trusted"], false);
+    assert_eq!(report["x_forwarded_for"], json!(["1.2.3.4"]));
+}
+
+#[actix_web::test]
+async fn test_client_ip_x_forwarded_for_chain() {
+    let report = client_report(
+        &["10.0.0.0/8", "192.0.2.1"],
+        test::TestRequest::get()
+            .peer_addr("10.0.0.2:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "6.6.6.6, 203.0.113.9, 192.0.2.1")),
+    )
+    .await;
+
+    // The spoofed first hop is left out, as 203.0.113.9 isn't trusted
+    assert_eq!(report["client_ip"], "203.0.113.9");
+    assert_eq!(report["source"], "x-forwarded-for");
+    assert_eq!(
+        report["chain"],
+        json!(["6.6.6.6", "203.0.113.9", "192.0.2.1", "10.0.0.2"])
+    );
+}
+
+#[actix_web::test]
+async fn test_client_ip_forwarded_header() {
+    let report = client_report(
+        &["::1"],
+        test::TestRequest::get()
+            .peer_addr("[::1]:4000".parse().unwrap())
+            .insert_header((
+                header::FORWARDED,
+                "for=\"[2001:db8::17]:4711\";proto=https;by=203.0.113.43",
+            ))
+            .insert_header(("X-Forwarded-For", "1.2.3.4")),
+    )
+    .await;
+
+    assert_eq!(report["client_ip"], "2001:db8::17");
+    assert_eq!(report["source"], "forwarded");
+    assert_eq!(report["forwarded"][0]["proto"], "https");
+    assert_eq!(report["forwarded"][0]["by"], "203.0.113.43");
+}
+
+#[actix_web::test]
+async fn test_client_ip_in_prepare_response() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(trusted(&["127.0.0.1"]))
+            .wrap(from_fn(client_ip))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/?json")
+        .peer_addr("127.0.0.1:4000".parse().unwrap())
+        .insert_header(("X-Real-IP", "192.0.2.44"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Value = test::read_body_json(resp).await;
+
+    assert_eq!(body["client"]["client_ip"], "192.0.2.44");
+    assert_eq!(body["client"]["source"], "x-real-ip");
+    assert_eq!(body["client"]["chain"], json!(["192.0.2.44", "127.0.0.1"]));
+}
+
+#[actix_web::test]
+async fn test_client_detail_keeps_etag() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .wrap(from_fn(client_ip))
+            .service(hello),
+    )
+    .await;
+
+    let mut responses = Vec::new();
+    for peer in ["198.51.100.7:4000", "198.51.100.7:4001"] {
+        let req = test::TestRequest::get()
+            .uri("/?json")
+            .peer_addr(peer.parse().unwrap())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+        let body: Value = test::read_body_json(resp).await;
+        responses.push((etag, body));
+    }
+
+    // The peer port is reported, but doesn't make another representation
+    assert_ne!(responses[0].1["client"], responses[1].1["client"]);
+    assert_eq!(responses[0].0, responses[1].0);
+}
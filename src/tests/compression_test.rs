@@ -0,0 +1,188 @@
+use super::super::*;
+use actix_web::{http, test, App};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{Read, Write};
+
+#[actix_web::test]
+async fn test_gzip_endpoint() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(gzip_body),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/gzip?json")
+        .insert_header(("X-Test", "yes"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "gzip"
+    );
+    let etag = resp.headers().get(header::ETAG).unwrap();
+    assert!(etag.to_str().unwrap().starts_with("W/\""));
+
+    let body = test::read_body(resp).await;
+    let mut decoded = String::new();
+    GzDecoder::new(body.as_ref())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_str(&decoded).unwrap();
+    assert_eq!(result["response"]["encoding"], "gzip");
+    assert_eq!(result["response"]["headers"]["x-test"], "yes");
+}
+
+#[actix_web::test]
+async fn test_brotli_endpoint() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(brotli_body),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/brotli?json").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.headers().get(header::CONTENT_ENCODING).unwrap(), "br");
+
+    let body = test::read_body(resp).await;
+    let mut decoded = String::new();
+    brotli::Decompressor::new(body.as_ref(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_str(&decoded).unwrap();
+    assert_eq!(result["response"]["encoding"], "br");
+}
+
+#[actix_web::test]
+async fn test_compression_limited_to_enabled_encodings() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(web::Data::new(CompressionConfig(vec![Encoding::Gzip])))
+            .wrap(Compress::default())
+            .wrap(from_fn(filter_accept_encoding))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header((header::ACCEPT_ENCODING, "br, gzip;q=0.5"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "gzip"
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header((header::ACCEPT_ENCODING, "br"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+}
+
+#[actix_web::test]
+async fn test_echo_compressed_body() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(echo),
+    )
+    .await;
+
+    let payload = json!({ "message": "a".repeat(1000) }).to_string();
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(payload.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/echo?json")
+        .insert_header(header::ContentType::json())
+        .insert_header((header::CONTENT_ENCODING, "gzip"))
+        .set_payload(compressed.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["message"], "a".repeat(1000));
+    assert_eq!(result["body"]["content_encoding"], "gzip");
+    assert_eq!(result["body"]["original_size"], compressed.len());
+    assert_eq!(result["body"]["decoded_size"], payload.len());
+}
+
+#[actix_web::test]
+async fn test_echo_requires_json_content_type() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(echo),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/echo")
+        .insert_header(header::ContentType::plaintext())
+        .set_payload("{}")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_encoded_etag_weak() {
+    // Without the connection details, whose sections already weaken the tag
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .wrap(Compress::default())
+            .wrap(from_fn(weaken_encoded_etag))
+            .service(cache),
+    )
+    .await;
+    let get = |accept_encoding: &str| {
+        test::TestRequest::get()
+            .uri("/cache?json")
+            .insert_header((header::ACCEPT_ENCODING, accept_encoding.to_string()))
+    };
+
+    let resp = test::call_service(&app, get("identity").to_request()).await;
+    let identity = resp.headers().get(header::ETAG).unwrap().clone();
+    assert!(identity.to_str().unwrap().starts_with('"'));
+
+    let resp = test::call_service(&app, get("gzip").to_request()).await;
+    assert_eq!(
+        resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "gzip"
+    );
+    let gzip = resp.headers().get(header::ETAG).unwrap().clone();
+    assert_eq!(gzip, format!("W/{}", identity.to_str().unwrap()));
+
+    // The weak tag still validates the cached copy, but isn't a strong match
+    let req = get("gzip")
+        .insert_header((header::IF_NONE_MATCH, gzip.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.headers().get(header::ETAG).unwrap(), gzip);
+
+    let req = get("gzip")
+        .insert_header((header::IF_MATCH, gzip.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PRECONDITION_FAILED);
+}
@@ -0,0 +1,93 @@
+use super::super::*;
+use std::fs;
+use utils::config::{load, parse_from};
+
+/// A configuration file named `name` holding `content`
+fn config_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustwester-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn parse(args: &[&str]) -> Result<(Cli, utils::config::EffectiveOptions)> {
+    parse_from(std::iter::once("rustwester").chain(args.iter().copied()))
+}
+
+#[actix_web::test]
+async fn test_load_toml_and_yaml() {
+    let toml = config_file("load.toml", "port = 8080\nlisten = [\"a:1\", \"b:2\"]\n");
+    let yaml = config_file("load.yaml", "port: 8080\nlisten:\n  - a:1\n  - b:2\n");
+
+    let expected = json!({ "port": 8080, "listen": ["a:1", "b:2"] });
+    assert_eq!(Value::Object(load(&toml).unwrap()), expected);
+    assert_eq!(Value::Object(load(&yaml).unwrap()), expected);
+    assert!(load(&config_file("load.ini", "port = 8080")).is_err());
+}
+
+#[actix_web::test]
+async fn test_file_below_command_line() {
+    let path = config_file(
+        "precedence.toml",
+        "port = 8080\nbind = \"127.0.0.1\"\nno_json = true\nverbose = 2\n",
+    );
+    let (cli, effective) = parse(&["--config", path.to_str().unwrap(), "--port", "7070"]).unwrap();
+
+    assert_eq!(cli.port, 7070);
+    assert_eq!(cli.bind, "127.0.0.1");
+    assert!(cli.no_json);
+    assert_eq!(cli.debug, 2);
+    assert_eq!(effective.sources["port"], "command line");
+    assert_eq!(effective.sources["bind"], "file");
+    assert_eq!(effective.sources["backlog"], "default");
+    assert_eq!(effective.options["port"], 7070);
+    assert_eq!(effective.options["no-json"], true);
+}
+
+#[actix_web::test]
+async fn test_file_satisfies_requirements() {
+    let path = config_file("requires.yaml", "admin-user: admin\n");
+    let config = path.to_str().unwrap();
+    assert!(matches!(
+        parse(&["--config", config]),
+        Err(WesterError::Config(_))
+    ));
+
+    let (cli, effective) = parse(&["--config", config, "--admin-password", "hunter2"]).unwrap();
+    assert_eq!(cli.admin_user.as_deref(), Some("admin"));
+    assert_eq!(effective.options["admin-password"], "<redacted>");
+    assert_eq!(effective.options["admin-user"], "admin");
+}
+
+#[actix_web::test]
+async fn test_invalid_file() {
+    for (name, content) in [
+        ("unknown.toml", "nope = 1\n"),
+        ("value.toml", "port = \"x\"\n"),
+        ("table.toml", "[port]\nvalue = 1\n"),
+        ("flag.toml", "no_json = \"yes\"\n"),
+    ] {
+        let path = config_file(name, content);
+        match parse(&["--config", path.to_str().unwrap()]) {
+            Err(WesterError::Config(message)) => assert!(message.contains(name), "{}", message),
+            _ => panic!("{} should be rejected", name),
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_render_effective_options() {
+    let path = config_file("render.toml", "port = 8080\njwt_secret = \"s3cr3t\"\n");
+    let (_, effective) = parse(&["--config", path.to_str().unwrap()]).unwrap();
+
+    let toml = effective.render(ConfigFormat::Toml).unwrap();
+    assert!(toml.contains("port = 8080"));
+    assert!(toml.contains("jwt-secret = \"<redacted>\""));
+    assert!(!toml.contains("s3cr3t"));
+
+    // A rendered configuration reads back the same
+    let path = config_file(
+        "rendered.yaml",
+        &effective.render(ConfigFormat::Yaml).unwrap(),
+    );
+    assert_eq!(load(&path).unwrap(), effective.options);
+}
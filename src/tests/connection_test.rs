@@ -0,0 +1,105 @@
+use super::super::*;
+use super::{get_alone, local_listener, spawn_service};
+use actix_web::{test, App};
+use server::ServerOptions;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn report_of(response: &str) -> Value {
+    let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+    let body: Value = serde_json::from_str(body).unwrap();
+    body["connection"].clone()
+}
+
+#[actix_web::test]
+async fn test_connection_reused() {
+    let (addr, listener) = local_listener(ListenerOptions::default());
+    let handle = spawn_service(
+        || {
+            App::new()
+                .app_data(web::Data::new(AppState { allow_json: true }))
+                .wrap(from_fn(connection))
+                .service(hello)
+        },
+        vec![listener],
+        &ServerOptions::default(),
+    );
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut reports = Vec::new();
+    for close in [false, true] {
+        let connection = if close { "close" } else { "keep-alive" };
+        stream
+            .write_all(
+                format!(
+                    "GET /?json HTTP/1.1\r\nHost: localhost\r\nConnection: {}\r\n\r\n",
+                    connection
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut buf = vec![0; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        reports.push(report_of(&String::from_utf8_lossy(&buf[..n])));
+    }
+    handle.stop(false).await;
+
+    assert_eq!(reports[0]["requests"], 1);
+    assert_eq!(reports[0]["reused"], false);
+    assert_eq!(reports[0]["keep_alive"], true);
+    assert_eq!(reports[0]["local_addr"], addr.to_string());
+    assert_eq!(reports[1]["requests"], 2);
+    assert_eq!(reports[1]["reused"], true);
+    assert_eq!(reports[1]["keep_alive"], false);
+    assert_eq!(reports[0]["id"], reports[1]["id"]);
+}
+
+#[actix_web::test]
+async fn test_connection_without_connection_data() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .wrap(from_fn(connection))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/?json")
+        .version(actix_web::http::Version::HTTP_10)
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(body["connection"]["http_version"], "HTTP/1.0");
+    assert_eq!(body["connection"]["keep_alive"], false);
+    assert!(body["connection"]["id"].is_null());
+}
+
+fn etag_of(response: &str) -> &str {
+    response
+        .lines()
+        .find_map(|line| line.strip_prefix("etag: "))
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_connection_detail_keeps_etag() {
+    let (addr, listener) = local_listener(ListenerOptions::default());
+    let handle = spawn_service(
+        || {
+            let app = App::new().app_data(web::Data::new(AppState { allow_json: true }));
+            wrap_middlewares(app, true).service(hello)
+        },
+        vec![listener],
+        &ServerOptions::default(),
+    );
+
+    let first = get_alone(addr, "/?json", &[]).await;
+    let second = get_alone(addr, "/?json", &[]).await;
+    handle.stop(false).await;
+
+    // Each connection and client port is reported, with the same validator
+    assert_ne!(report_of(&first)["id"], report_of(&second)["id"]);
+    assert_eq!(etag_of(&first), etag_of(&second));
+}
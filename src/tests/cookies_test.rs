@@ -0,0 +1,192 @@
+use super::super::*;
+use actix_web::cookie::Cookie;
+use actix_web::{http, test, App};
+
+fn set_cookie_headers<B>(resp: &actix_web::dev::ServiceResponse<B>) -> Vec<String> {
+    resp.headers()
+        .get_all(header::SET_COOKIE)
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_cookies_echo() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(get_cookies),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/cookies?json")
+        .cookie(Cookie::new("theme", "dark"))
+        .cookie(Cookie::new("lang", "en"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["cookies"]["theme"], "dark");
+    assert_eq!(result["response"]["cookies"]["lang"], "en");
+}
+
+#[actix_web::test]
+async fn test_cookies_set_with_attributes() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(set_cookies),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/cookies/set?theme=dark&secure&httponly&samesite=strict&domain=example.com&path=/app&max_age=60&json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let cookies = set_cookie_headers(&resp);
+    assert_eq!(cookies.len(), 1);
+    let cookie = Cookie::parse(cookies[0].clone()).unwrap();
+    assert_eq!(cookie.name(), "theme");
+    assert_eq!(cookie.value(), "dark");
+    assert_eq!(cookie.secure(), Some(true));
+    assert_eq!(cookie.http_only(), Some(true));
+    assert_eq!(
+        cookie.same_site(),
+        Some(actix_web::cookie::SameSite::Strict)
+    );
+    assert_eq!(cookie.domain(), Some("example.com"));
+    assert_eq!(cookie.path(), Some("/app"));
+    assert_eq!(
+        cookie.max_age(),
+        Some(actix_web::cookie::time::Duration::seconds(60))
+    );
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["set"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_cookies_set_invalid_samesite() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(set_cookies),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/cookies/set?theme=dark&samesite=sometimes")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_cookies_delete() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(delete_cookies),
+    )
+    .await;
+
+    // Without names every cookie sent with the request is removed
+    let req = test::TestRequest::get()
+        .uri("/cookies/delete?json")
+        .cookie(Cookie::new("theme", "dark"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    let cookies = set_cookie_headers(&resp);
+    assert_eq!(cookies.len(), 1);
+    let cookie = Cookie::parse(cookies[0].clone()).unwrap();
+    assert_eq!(cookie.name(), "theme");
+    assert_eq!(cookie.value(), "");
+    assert_eq!(
+        cookie.max_age(),
+        Some(actix_web::cookie::time::Duration::ZERO)
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/cookies/delete?a&b")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(set_cookie_headers(&resp).len(), 2);
+}
+
+#[actix_web::test]
+async fn test_sticky_session() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(web::Data::new(StickyCookie("instance".to_string())))
+            .wrap(from_fn(sticky_session))
+            .service(hello),
+    )
+    .await;
+    let hostname = get_hostname().await;
+
+    // First response sets the cookie
+    let req = test::TestRequest::get().uri("/?json").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.headers().get("x-sticky-session").unwrap(), "new");
+    let cookie = Cookie::parse(set_cookie_headers(&resp)[0].clone()).unwrap();
+    assert_eq!(cookie.name(), "instance");
+    assert_eq!(cookie.value(), hostname);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["sticky"]["status"], "new");
+    assert_eq!(result["sticky"]["instance"], hostname);
+
+    // Coming back to the same instance
+    let req = test::TestRequest::get()
+        .uri("/?json")
+        .cookie(Cookie::new("instance", hostname.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.headers().get("x-sticky-session").unwrap(),
+        "same-instance"
+    );
+    assert!(set_cookie_headers(&resp).is_empty());
+
+    // Landing on another instance
+    let req = test::TestRequest::get()
+        .uri("/?json")
+        .cookie(Cookie::new("instance", "some-other-pod"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["sticky"]["status"], "different-instance");
+    assert_eq!(result["sticky"]["first_instance"], "some-other-pod");
+}
+
+#[actix_web::test]
+async fn test_sticky_session_disabled() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .wrap(from_fn(sticky_session))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/?json").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(resp.headers().get("x-sticky-session").is_none());
+    assert!(set_cookie_headers(&resp).is_empty());
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert!(result.get("sticky").is_none());
+}
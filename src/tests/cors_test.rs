@@ -0,0 +1,212 @@
+use super::super::*;
+use actix_web::{http, test, App};
+
+fn cors_config(origins: &[&str], headers: &[&str], credentials: bool) -> web::Data<CorsConfig> {
+    web::Data::new(
+        CorsConfig::new(
+            origins.iter().map(|o| o.to_string()).collect(),
+            vec!["GET".to_string(), "POST".to_string()],
+            headers.iter().map(|h| h.to_string()).collect(),
+            credentials,
+            Some(600),
+        )
+        .unwrap(),
+    )
+}
+
+#[actix_web::test]
+async fn test_cors_preflight() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(cors_config(&["https://*.example.com"], &[], false))
+            .wrap(from_fn(cors))
+            .service(echo),
+    )
+    .await;
+
+    let req = test::TestRequest::default()
+        .method(http::Method::OPTIONS)
+        .uri("/echo")
+        .insert_header((header::ORIGIN, "https://app.example.com"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+        .insert_header((
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            "Content-Type, X-Custom",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let headers = resp.headers();
+    assert_eq!(
+        headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        "https://app.example.com"
+    );
+    assert_eq!(
+        headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+        "GET, POST"
+    );
+    assert_eq!(
+        headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap(),
+        "content-type, x-custom"
+    );
+    assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+
+    let req = test::TestRequest::default()
+        .method(http::Method::OPTIONS)
+        .uri("/echo")
+        .insert_header((header::ORIGIN, "https://evil.com"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    assert!(resp
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
+
+#[actix_web::test]
+async fn test_cors_simple_request() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(cors_config(&["*"], &[], false))
+            .wrap(from_fn(cors))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header((header::ORIGIN, "https://anything.test"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .unwrap(),
+        "*"
+    );
+
+    // Without an Origin header nothing is added
+    let req = test::TestRequest::get().uri("/").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(resp
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
+
+#[actix_web::test]
+async fn test_cors_credentials_echo_origin() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(cors_config(&["~^https://[a-z]+\\.test$"], &[], true))
+            .wrap(from_fn(cors))
+            .service(hello),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header((header::ORIGIN, "https://front.test"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    let headers = resp.headers();
+    assert_eq!(
+        headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        "https://front.test"
+    );
+    assert_eq!(
+        headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .unwrap(),
+        "true"
+    );
+    assert_eq!(headers.get(header::VARY).unwrap(), "Origin");
+}
+
+#[actix_web::test]
+async fn test_cors_report() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(cors_config(
+                &["https://front.test"],
+                &["content-type"],
+                false,
+            ))
+            .wrap(from_fn(cors))
+            .configure(routes::cors::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::default()
+        .method(http::Method::OPTIONS)
+        .uri("/cors?json")
+        .insert_header((header::ORIGIN, "https://front.test"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "x-denied"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    let report = &result["response"];
+    assert_eq!(report["request"]["preflight"], true);
+    assert_eq!(
+        report["request"]["access_control_request_headers"],
+        "x-denied"
+    );
+    assert_eq!(report["policy"]["headers"][0], "content-type");
+    assert_eq!(report["decision"]["allowed"], false);
+    assert_eq!(
+        report["decision"]["reason"],
+        "Header x-denied is not allowed"
+    );
+}
+
+#[actix_web::test]
+async fn test_cors_report_disabled() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .wrap(from_fn(cors))
+            .configure(routes::cors::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/cors?json")
+        .insert_header((header::ORIGIN, "https://front.test"))
+        .to_request();
+    let result: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(
+        result["response"]["decision"]["reason"],
+        "CORS is not enabled"
+    );
+    assert!(result["response"]["policy"].is_null());
+}
+
+#[actix_web::test]
+async fn test_cors_invalid_config() {
+    assert!(CorsConfig::new(vec!["~(".to_string()], vec![], vec![], false, None).is_err());
+    assert!(CorsConfig::new(
+        vec!["*".to_string()],
+        vec!["GE T".to_string()],
+        vec![],
+        false,
+        None
+    )
+    .is_err());
+}
@@ -0,0 +1,90 @@
+use super::super::*;
+use actix_web::body::MessageBody;
+use actix_web::{http, test, App};
+use std::time::{Duration, Instant};
+
+#[actix_web::test]
+async fn test_parse_size() {
+    assert_eq!(parse_rate("512").unwrap(), 512);
+    assert_eq!(routes::download::parse_size("10KB").unwrap(), 10_000);
+    assert_eq!(routes::download::parse_size("1.5MiB").unwrap(), 1_572_864);
+    assert_eq!(routes::download::parse_size("1GiB").unwrap(), 1 << 30);
+    assert_eq!(routes::download::parse_size("0").unwrap(), 0);
+    assert!(routes::download::parse_size("1XB").is_err());
+    assert!(routes::download::parse_size("lots").is_err());
+    assert!(parse_rate("0").is_err());
+}
+
+#[actix_web::test]
+async fn test_download() {
+    let app = test::init_service(App::new().service(download)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/download/100KiB")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/octet-stream"
+    );
+
+    let body = test::read_body(resp).await;
+    assert_eq!(body.len(), 100 * 1024);
+    assert!(body
+        .iter()
+        .enumerate()
+        .all(|(i, b)| *b == b'a' + (i % 26) as u8));
+
+    let req = test::TestRequest::get().uri("/download/huge").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_download_rate() {
+    let app = test::init_service(App::new().service(download)).await;
+
+    // 3000 bytes at 10000 bytes/s take 300ms
+    let started = Instant::now();
+    let req = test::TestRequest::get()
+        .uri("/download/3000?rate=10KB")
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+
+    assert_eq!(body.len(), 3000);
+    assert!(started.elapsed() >= Duration::from_millis(280));
+
+    let req = test::TestRequest::get()
+        .uri("/download/3000?rate=0")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_max_bandwidth() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(web::Data::new(MaxBandwidth(10_000)))
+            .wrap(from_fn(limit_bandwidth))
+            .service(range),
+    )
+    .await;
+
+    let started = Instant::now();
+    let req = test::TestRequest::get().uri("/range/3000").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    // The size is kept, so is the Content-Length
+    assert_eq!(
+        resp.response().body().size(),
+        actix_web::body::BodySize::Sized(3000)
+    );
+    assert_eq!(test::read_body(resp).await.len(), 3000);
+    assert!(started.elapsed() >= Duration::from_millis(280));
+}
@@ -0,0 +1,58 @@
+use super::super::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+#[actix_web::test]
+async fn test_tcp_echo() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(server::echo::tcp(listener, false).unwrap());
+
+    let mut io = TcpStream::connect(addr).await.unwrap();
+    io.write_all(b"ping\0\xff").await.unwrap();
+    let mut echoed = [0; 6];
+    io.read_exact(&mut echoed).await.unwrap();
+    io.shutdown().await.unwrap();
+    assert_eq!(io.read(&mut echoed).await.unwrap(), 0);
+    server.abort();
+
+    assert_eq!(&echoed, b"ping\0\xff");
+}
+
+#[actix_web::test]
+async fn test_tcp_echo_prefix() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(server::echo::tcp(listener, true).unwrap());
+
+    let mut io = TcpStream::connect(addr).await.unwrap();
+    io.write_all(b"ping").await.unwrap();
+    io.shutdown().await.unwrap();
+    let mut echoed = Vec::new();
+    io.read_to_end(&mut echoed).await.unwrap();
+    server.abort();
+
+    let expected = format!("{} {} ping", get_hostname().await, io.local_addr().unwrap());
+    assert_eq!(String::from_utf8(echoed).unwrap(), expected);
+}
+
+#[actix_web::test]
+async fn test_udp_echo() {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let server = tokio::spawn(server::echo::udp(socket, true).unwrap());
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.send_to(b"ping", addr).await.unwrap();
+    let mut buf = [0; 1024];
+    let (n, from) = client.recv_from(&mut buf).await.unwrap();
+    server.abort();
+
+    assert_eq!(from, addr);
+    let expected = format!(
+        "{} {} ping",
+        get_hostname().await,
+        client.local_addr().unwrap()
+    );
+    assert_eq!(String::from_utf8_lossy(&buf[..n]), expected);
+}
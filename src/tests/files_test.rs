@@ -0,0 +1,117 @@
+use super::super::*;
+use actix_web::{http, test, App};
+use std::fs;
+
+/// A fresh directory with a text file and a subdirectory
+fn serve_dir(name: &str) -> ServeDir {
+    let dir = std::env::temp_dir().join(format!("rustwester-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("sub dir")).unwrap();
+    fs::write(dir.join("hello.txt"), "Hello, files!").unwrap();
+    ServeDir::new(dir, "/files/").unwrap()
+}
+
+#[actix_web::test]
+async fn test_serve_dir_listing_json() {
+    let serve_dir = serve_dir("listing-json");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .configure(|cfg| routes::files::configure(cfg, &serve_dir)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/files/?json").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["path"], "/files/");
+    let entries = result["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["name"], "sub dir");
+    assert_eq!(entries[0]["type"], "directory");
+    assert_eq!(entries[0]["href"], "/files/sub%20dir/");
+    assert_eq!(entries[1]["name"], "hello.txt");
+    assert_eq!(entries[1]["size"], 13);
+}
+
+#[actix_web::test]
+async fn test_serve_dir_listing_html() {
+    let serve_dir = serve_dir("listing-html");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: false }))
+            .configure(|cfg| routes::files::configure(cfg, &serve_dir)),
+    )
+    .await;
+
+    // JSON is disabled, so the page is rendered anyway
+    let req = test::TestRequest::get()
+        .uri("/files/sub%20dir/")
+        .insert_header(header::Accept::json())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/html; charset=utf-8"
+    );
+    let body = test::read_body(resp).await;
+    let body_str = std::str::from_utf8(&body).unwrap();
+    assert!(body_str.contains("Index of /files/sub%20dir/"));
+    assert!(body_str.contains("<a href=\"..\">"));
+
+    let req = test::TestRequest::get().uri("/files").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(resp.headers().get(header::LOCATION).unwrap(), "/files/");
+}
+
+#[actix_web::test]
+async fn test_serve_dir_file() {
+    let serve_dir = serve_dir("file");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .configure(|cfg| routes::files::configure(cfg, &serve_dir)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/files/hello.txt")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    let etag = resp.headers().get(header::ETAG).unwrap().clone();
+    assert_eq!(test::read_body(resp).await, "Hello, files!");
+
+    let req = test::TestRequest::get()
+        .uri("/files/hello.txt")
+        .insert_header((header::RANGE, "bytes=7-11"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(test::read_body(resp).await, "files");
+
+    let req = test::TestRequest::get()
+        .uri("/files/hello.txt")
+        .insert_header((header::IF_NONE_MATCH, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+}
+
+#[actix_web::test]
+async fn test_serve_dir_missing() {
+    assert!(ServeDir::new("/nonexistent/rustwester".into(), "/files").is_err());
+}
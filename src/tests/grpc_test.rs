@@ -0,0 +1,129 @@
+use super::super::*;
+use actix_web::{test, App};
+use server::grpc::{EchoRequest, EchoResponse, EchoServer};
+use tonic::server::NamedService;
+use tonic::transport::Channel;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+use tonic_reflection::pb::v1::server_reflection_client::ServerReflectionClient;
+use tonic_reflection::pb::v1::server_reflection_request::MessageRequest;
+use tonic_reflection::pb::v1::server_reflection_response::MessageResponse;
+use tonic_reflection::pb::v1::ServerReflectionRequest;
+
+/// Start the gRPC listener on a free port, and connect to it
+async fn start(health: HealthReporter) -> Channel {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = server::grpc::serve(listener, health).unwrap();
+    tokio::spawn(server);
+    Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+async fn check(channel: &Channel, service: &str) -> ServingStatus {
+    let response = HealthClient::new(channel.clone())
+        .check(HealthCheckRequest {
+            service: service.to_string(),
+        })
+        .await
+        .unwrap();
+    response.into_inner().status()
+}
+
+#[actix_web::test]
+async fn test_grpc_echo() {
+    let channel = start(HealthReporter::new()).await;
+
+    let mut request = tonic::Request::new(EchoRequest {
+        message: "hello".to_string(),
+        payload: vec![0, 1, 2],
+    });
+    request
+        .metadata_mut()
+        .insert("x-test", "grpc".parse().unwrap());
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await.unwrap();
+    let response: tonic::Response<EchoResponse> = client
+        .unary(
+            request,
+            "/rustwester.echo.v1.Echo/Echo".parse().unwrap(),
+            tonic_prost::ProstCodec::default(),
+        )
+        .await
+        .unwrap();
+    let response = response.into_inner();
+
+    assert_eq!(response.message, "hello");
+    assert_eq!(response.payload, vec![0, 1, 2]);
+    assert_eq!(response.hostname, get_hostname().await);
+    assert!(response.peer.starts_with("127.0.0.1:"));
+    assert!(response
+        .metadata
+        .iter()
+        .any(|entry| entry.key == "x-test" && entry.value == "grpc"));
+}
+
+#[actix_web::test]
+async fn test_grpc_health_switched_by_admin() {
+    let health = HealthReporter::new();
+    let channel = start(health.clone()).await;
+    assert_eq!(check(&channel, "").await, ServingStatus::Serving);
+    assert_eq!(
+        check(&channel, EchoServer::NAME).await,
+        ServingStatus::Serving
+    );
+
+    let state = web::Data::new(AdminState {
+        grpc_health: Some(health),
+        ..AdminState::new(Some("admin-token".to_string()), None, None)
+    });
+    let app = test::init_service(
+        App::new().configure(|cfg| routes::admin::configure(cfg, state.clone())),
+    )
+    .await;
+    let req = test::TestRequest::put()
+        .uri("/_admin/grpc-health?status=not_serving")
+        .insert_header((header::AUTHORIZATION, "Bearer admin-token"))
+        .to_request();
+    let result: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(result["status"], "NotServing");
+    assert_eq!(check(&channel, "").await, ServingStatus::NotServing);
+    assert_eq!(
+        check(&channel, EchoServer::NAME).await,
+        ServingStatus::Serving
+    );
+
+    let req = test::TestRequest::put()
+        .uri("/_admin/grpc-health?status=bogus")
+        .insert_header((header::AUTHORIZATION, "Bearer admin-token"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_grpc_reflection_lists_services() {
+    let channel = start(HealthReporter::new()).await;
+
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = ServerReflectionClient::new(channel)
+        .server_reflection_info(futures_util::stream::iter([request]))
+        .await
+        .unwrap()
+        .into_inner();
+    let response = responses.message().await.unwrap().unwrap();
+    let Some(MessageResponse::ListServicesResponse(list)) = response.message_response else {
+        panic!("Unexpected reflection response {:?}", response);
+    };
+    let services: Vec<_> = list.service.into_iter().map(|s| s.name).collect();
+
+    assert!(services.contains(&"grpc.health.v1.Health".to_string()));
+    assert!(services.contains(&"rustwester.echo.v1.Echo".to_string()));
+}
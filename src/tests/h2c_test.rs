@@ -0,0 +1,156 @@
+use super::super::*;
+use super::{local_listener, spawn_service};
+use actix_web::web::Bytes;
+use actix_web::App;
+use server::{ListenerOptions, ServerOptions};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Start the app with h2c on a free port
+fn start() -> (SocketAddr, actix_web::dev::ServerHandle) {
+    let (addr, listener) = local_listener(ListenerOptions {
+        h2c: true,
+        ..Default::default()
+    });
+    let handle = spawn_service(
+        || {
+            App::new()
+                .app_data(web::Data::new(AppState { allow_json: true }))
+                .wrap(from_fn(connection))
+                .service(hello)
+                .service(echo)
+        },
+        vec![listener],
+        &ServerOptions::default(),
+    );
+    (addr, handle)
+}
+
+/// Send requests with prior knowledge, all on the same connection
+async fn prior_knowledge(
+    addr: SocketAddr,
+    requests: Vec<(http::Request<()>, Bytes)>,
+) -> Vec<Value> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut client, connection) = h2::client::handshake(stream).await.unwrap();
+    actix_web::rt::spawn(connection);
+
+    let mut bodies = Vec::new();
+    for (req, body) in requests {
+        let (response, mut send) = client.send_request(req, body.is_empty()).unwrap();
+        if !body.is_empty() {
+            send.send_data(body, true).unwrap();
+        }
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let mut body = response.into_body();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.unwrap();
+            body.flow_control().release_capacity(chunk.len()).unwrap();
+            data.extend_from_slice(&chunk);
+        }
+        bodies.push(serde_json::from_slice(&data).unwrap());
+    }
+    bodies
+}
+
+/// Read the frames of `stream` until it ends, and return its DATA
+async fn read_stream(io: &mut TcpStream, stream: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    loop {
+        let mut header = [0u8; 9];
+        io.read_exact(&mut header).await.unwrap();
+        let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let mut payload = vec![0; len];
+        io.read_exact(&mut payload).await.unwrap();
+        let id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+        if id == stream && header[3] == 0x0 {
+            data.extend_from_slice(&payload);
+        }
+        if id == stream && header[4] & 0x1 != 0 {
+            return data;
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_h2c_prior_knowledge_hello_and_echo() {
+    let (addr, handle) = start();
+
+    let hello_request = http::Request::get(format!("http://{}/?json", addr))
+        .body(())
+        .unwrap();
+    let echo_request = http::Request::post(format!("http://{}/echo?json", addr))
+        .header("content-type", "application/json")
+        .body(())
+        .unwrap();
+    let bodies = prior_knowledge(
+        addr,
+        vec![
+            (hello_request, Bytes::new()),
+            (echo_request, Bytes::from_static(b"{\"hello\":\"h2c\"}")),
+        ],
+    )
+    .await;
+    handle.stop(false).await;
+
+    assert_eq!(bodies[0]["response"], "Hello world");
+    assert_eq!(bodies[0]["connection"]["http_version"], "HTTP/2.0");
+    assert_eq!(bodies[0]["connection"]["h2c"], "prior-knowledge");
+    assert_eq!(bodies[0]["connection"]["stream_id"], 1);
+    assert_eq!(bodies[0]["connection"]["stream_id_exact"], false);
+    assert_eq!(bodies[1]["response"], json!({ "hello": "h2c" }));
+    assert_eq!(bodies[1]["connection"]["stream_id"], 3);
+    assert_eq!(bodies[1]["connection"]["reused"], true);
+}
+
+#[actix_web::test]
+async fn test_h2c_upgrade_echo() {
+    let (addr, handle) = start();
+
+    let mut io = TcpStream::connect(addr).await.unwrap();
+    io.write_all(
+        b"POST /echo?json HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+          Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\nContent-Type: application/json\r\n\
+          Content-Length: 11\r\n\r\n{\"up\":true}",
+    )
+    .await
+    .unwrap();
+    let mut switching = vec![0; 71];
+    io.read_exact(&mut switching).await.unwrap();
+    assert!(switching.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+
+    // The connection preface, then an empty SETTINGS frame
+    io.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0\0\x04\0\0\0\0\0")
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&read_stream(&mut io, 1).await).unwrap();
+    handle.stop(false).await;
+
+    assert_eq!(body["response"], json!({ "up": true }));
+    assert_eq!(body["connection"]["http_version"], "HTTP/2.0");
+    assert_eq!(body["connection"]["h2c"], "upgrade");
+    assert_eq!(body["connection"]["stream_id"], 1);
+}
+
+#[actix_web::test]
+async fn test_h2c_plain_http1() {
+    let (addr, handle) = start();
+
+    let mut io = TcpStream::connect(addr).await.unwrap();
+    io.write_all(b"GET /?json HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    io.read_to_end(&mut response).await.unwrap();
+    handle.stop(false).await;
+
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    let body: Value = serde_json::from_str(response.split_once("\r\n\r\n").unwrap().1).unwrap();
+    assert!(body["connection"]["h2c"].is_null());
+    assert!(body["connection"]["stream_id"].is_null());
+    assert!(body["connection"]["stream_id_exact"].is_null());
+}
@@ -0,0 +1,168 @@
+use super::super::*;
+use super::{local_listener, spawn_service};
+use actix_web::web::Bytes;
+use actix_web::App;
+use bytes::Buf;
+use rustls::pki_types::CertificateDer;
+use server::h3::{alt_svc, AltSvc};
+use server::{ListenerOptions, ServerOptions};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A self-signed certificate for `localhost`, and the server configuration
+/// loaded from its PEM files
+fn certificate(name: &str) -> (CertificateDer<'static>, rustls::ServerConfig) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("rustwester-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("cert.pem"), certified.cert.pem()).unwrap();
+    std::fs::write(dir.join("key.pem"), certified.signing_key.serialize_pem()).unwrap();
+    let config = server::tls::load(&dir.join("cert.pem"), &dir.join("key.pem")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (certified.cert.der().clone(), config)
+}
+
+/// Client configuration trusting `cert` only
+fn client_config(cert: CertificateDer<'static>, alpn: &[u8]) -> rustls::ClientConfig {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    tls.alpn_protocols = vec![alpn.to_vec()];
+    tls
+}
+
+fn app() -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(web::Data::new(AppState { allow_json: true }))
+        .app_data(web::Data::new(AltSvc::new(443)))
+        .wrap(from_fn(alt_svc))
+        .wrap(from_fn(connection))
+        .service(hello)
+        .service(echo)
+}
+
+/// Send requests over a single HTTP/3 connection, returning the bodies of
+/// the responses and their `Alt-Svc` header
+async fn send(
+    addr: SocketAddr,
+    cert: CertificateDer<'static>,
+    requests: Vec<(http::Request<()>, Bytes)>,
+) -> Vec<(Value, Option<http::HeaderValue>)> {
+    let tls = client_config(cert, b"h3");
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap();
+
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+    let conn = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    let (mut driver, mut client) = h3::client::new(h3_quinn::Connection::new(conn))
+        .await
+        .unwrap();
+    actix_web::rt::spawn(async move {
+        std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+
+    let mut responses = Vec::new();
+    for (req, body) in requests {
+        let mut stream = client.send_request(req).await.unwrap();
+        if !body.is_empty() {
+            stream.send_data(body).await.unwrap();
+        }
+        stream.finish().await.unwrap();
+        let response = stream.recv_response().await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let mut data = Vec::new();
+        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+            data.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+        }
+        responses.push((
+            serde_json::from_slice(&data).unwrap(),
+            response.headers().get("alt-svc").cloned(),
+        ));
+    }
+    endpoint.close(0u32.into(), b"done");
+    responses
+}
+
+#[actix_web::test]
+async fn test_h3_hello_and_echo() {
+    let (cert, config) = certificate("h3");
+    let (addr, h3_server) =
+        server::h3::serve(app, "127.0.0.1:0".parse().unwrap(), &config).unwrap();
+    let h3_server = actix_web::rt::spawn(h3_server);
+
+    let hello_request = http::Request::get(format!("https://localhost:{}/?json", addr.port()))
+        .body(())
+        .unwrap();
+    let echo_request = http::Request::post(format!("https://localhost:{}/echo?json", addr.port()))
+        .header("content-type", "application/json")
+        .body(())
+        .unwrap();
+    let responses = send(
+        addr,
+        cert,
+        vec![
+            (hello_request, Bytes::new()),
+            (echo_request, Bytes::from_static(b"{\"hello\":\"h3\"}")),
+        ],
+    )
+    .await;
+    h3_server.abort();
+
+    let (hello_body, hello_alt_svc) = &responses[0];
+    assert_eq!(hello_body["response"], "Hello world");
+    assert_eq!(hello_body["connection"]["http_version"], "HTTP/3.0");
+    assert_eq!(hello_body["connection"]["stream_id"], 0);
+    assert_eq!(hello_body["connection"]["stream_id_exact"], true);
+    assert_eq!(hello_body["connection"]["requests"], 1);
+    // Only the TCP listener advertises the QUIC one
+    assert!(hello_alt_svc.is_none());
+    let (echo_body, _) = &responses[1];
+    assert_eq!(echo_body["response"], json!({ "hello": "h3" }));
+    assert_eq!(echo_body["connection"]["stream_id"], 4);
+    assert_eq!(echo_body["connection"]["reused"], true);
+}
+
+#[actix_web::test]
+async fn test_tls_listener_advertises_h3() {
+    let (cert, config) = certificate("tls");
+    let (addr, listener) = local_listener(ListenerOptions {
+        tls: Some(server::tls::with_alpn(&config, &[b"h2", b"http/1.1"])),
+        ..Default::default()
+    });
+    let handle = spawn_service(app, vec![listener], &ServerOptions::default());
+
+    let tls = client_config(cert, b"http/1.1");
+    let io = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut io = tokio_rustls::TlsConnector::from(Arc::new(tls))
+        .connect("localhost".try_into().unwrap(), io)
+        .await
+        .unwrap();
+    io.write_all(b"GET /?json HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    // The server closes without `close_notify`
+    let _ = io.read_to_end(&mut response).await;
+    handle.stop(false).await;
+
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("alt-svc: h3=\":443\"; ma=86400\r\n"));
+    let body: Value = serde_json::from_str(response.split_once("\r\n\r\n").unwrap().1).unwrap();
+    assert_eq!(body["connection"]["http_version"], "HTTP/1.1");
+}
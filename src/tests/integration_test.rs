@@ -1,5 +1,5 @@
 use super::super::*;
-use actix_web::{test, App};
+use actix_web::{http, test, App};
 use gethostname::gethostname;
 use serde_json::json;
 
@@ -433,7 +433,8 @@ async fn test_echo_form_contains_script() {
 
 #[actix_web::test]
 async fn test_render_markup_with_hello() {
-    let markup = super::super::render_markup("test-host", "Mozilla", Some("Hi"), None).await;
+    let markup =
+        super::super::render_markup("test-host", "Mozilla", Some("Hi"), None, &Map::new()).await;
     let html_str = markup.into_string();
 
     assert!(html_str.contains("Hi from test-host"));
@@ -445,7 +446,8 @@ async fn test_render_markup_with_hello() {
 #[actix_web::test]
 async fn test_render_markup_with_echo() {
     let echo_value = json!({"key": "value"});
-    let markup = super::super::render_markup("my-host", "Chrome", None, Some(echo_value)).await;
+    let markup =
+        super::super::render_markup("my-host", "Chrome", None, Some(echo_value), &Map::new()).await;
     let html_str = markup.into_string();
 
     assert!(html_str.contains("Hello world from my-host"));
@@ -459,9 +461,14 @@ async fn test_render_markup_with_echo() {
 #[actix_web::test]
 async fn test_render_markup_with_both() {
     let echo_value = json!({"test": 123});
-    let markup =
-        super::super::render_markup("prod-server", "Safari", Some("Welcome"), Some(echo_value))
-            .await;
+    let markup = super::super::render_markup(
+        "prod-server",
+        "Safari",
+        Some("Welcome"),
+        Some(echo_value),
+        &Map::new(),
+    )
+    .await;
     let html_str = markup.into_string();
 
     assert!(html_str.contains("Welcome from prod-server"));
@@ -473,7 +480,7 @@ async fn test_render_markup_with_both() {
 
 #[actix_web::test]
 async fn test_render_markup_html_structure() {
-    let markup = super::super::render_markup("localhost", "Test", None, None).await;
+    let markup = super::super::render_markup("localhost", "Test", None, None, &Map::new()).await;
     let html_str = markup.into_string();
 
     // Check for proper HTML structure
@@ -0,0 +1,161 @@
+use super::super::*;
+use actix_web::{http, test, App};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::json;
+
+fn hs256_token(claims: &serde_json::Value, secret: &str) -> String {
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .unwrap()
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+#[actix_web::test]
+async fn test_jwt_no_token() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(web::Data::new(JwtConfig::default()))
+            .service(jwt_inspect),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/jwt?json").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["error"], "No token found");
+}
+
+#[actix_web::test]
+async fn test_jwt_bearer_decoded_without_key() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(web::Data::new(JwtConfig::default()))
+            .service(jwt_inspect),
+    )
+    .await;
+
+    let token = hs256_token(&json!({"sub": "alice", "exp": now() + 600}), "secret");
+    let req = test::TestRequest::get()
+        .uri("/jwt")
+        .insert_header(header::Accept::json())
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    let report = &result["response"];
+    assert_eq!(report["source"], "header:authorization");
+    assert_eq!(report["header"]["alg"], "HS256");
+    assert_eq!(report["claims"]["sub"], "alice");
+    assert_eq!(report["expired"], false);
+    assert!(report["times"]["exp"]["relative"]
+        .as_str()
+        .unwrap()
+        .starts_with("in "));
+    assert_eq!(report["signature"]["checked"], false);
+}
+
+#[actix_web::test]
+async fn test_jwt_expired_and_not_yet_valid() {
+    let config = JwtConfig::default();
+    let token = hs256_token(&json!({"exp": now() - 120, "nbf": now() + 120}), "secret");
+
+    let report = routes::jwt::inspect_token(&token, &config, chrono::Utc::now());
+
+    assert_eq!(report["expired"], true);
+    assert_eq!(report["not_yet_valid"], true);
+    assert!(report["times"]["exp"]["relative"]
+        .as_str()
+        .unwrap()
+        .ends_with(" ago"));
+}
+
+#[actix_web::test]
+async fn test_jwt_hmac_signature() {
+    let config = JwtConfig::new(None, None, Some("secret".to_string()), None).unwrap();
+
+    let valid = routes::jwt::inspect_token(
+        &hs256_token(&json!({"sub": "bob"}), "secret"),
+        &config,
+        chrono::Utc::now(),
+    );
+    assert_eq!(valid["signature"]["checked"], true);
+    assert_eq!(valid["signature"]["valid"], true);
+
+    let invalid = routes::jwt::inspect_token(
+        &hs256_token(&json!({"sub": "bob"}), "other"),
+        &config,
+        chrono::Utc::now(),
+    );
+    assert_eq!(invalid["signature"]["checked"], true);
+    assert_eq!(invalid["signature"]["valid"], false);
+}
+
+#[actix_web::test]
+async fn test_jwt_cookie_from_query() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(web::Data::new(JwtConfig::default()))
+            .service(jwt_inspect),
+    )
+    .await;
+
+    let token = hs256_token(&json!({"sub": "carol"}), "secret");
+    let req = test::TestRequest::get()
+        .uri("/jwt?json&cookie=session")
+        .cookie(actix_web::cookie::Cookie::new("session", token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["source"], "cookie:session");
+    assert_eq!(result["response"]["claims"]["sub"], "carol");
+}
+
+#[actix_web::test]
+async fn test_jwt_malformed_token() {
+    let report =
+        routes::jwt::inspect_token("not-a-token", &JwtConfig::default(), chrono::Utc::now());
+
+    assert!(report["error"].as_str().unwrap().contains("found 1"));
+}
+
+#[actix_web::test]
+async fn test_jwt_html() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: false }))
+            .app_data(web::Data::new(JwtConfig::default()))
+            .service(jwt_inspect),
+    )
+    .await;
+
+    let token = hs256_token(&json!({"sub": "dave"}), "secret");
+    let req = test::TestRequest::get()
+        .uri("/jwt")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/html; charset=utf-8"
+    );
+
+    let body = test::read_body(resp).await;
+    let body_str = std::str::from_utf8(&body).unwrap();
+    assert!(body_str.contains("JWT inspection from"));
+    assert!(body_str.contains("dave"));
+}
@@ -0,0 +1,52 @@
+use super::super::*;
+use actix_web::{test, App, HttpResponse};
+
+async fn in_flight(lifecycle: web::Data<Lifecycle>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "in_flight": lifecycle.in_flight() }))
+}
+
+#[actix_web::test]
+async fn test_readyz_fails_when_draining() {
+    let lifecycle = web::Data::new(Lifecycle::default());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(track_in_flight))
+            .configure(|cfg| routes::lifecycle::configure(cfg, lifecycle.clone())),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "ready": true, "in_flight": 1 }));
+
+    lifecycle.start_draining();
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+    );
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["ready"], false);
+}
+
+#[actix_web::test]
+async fn test_requests_in_flight_until_body_sent() {
+    let lifecycle = web::Data::new(Lifecycle::default());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(track_in_flight))
+            .app_data(lifecycle.clone())
+            .route("/in-flight", web::get().to(in_flight)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/in-flight").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(lifecycle.in_flight(), 1);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["in_flight"], 1);
+    assert_eq!(lifecycle.in_flight(), 0);
+}
@@ -0,0 +1,151 @@
+use super::super::*;
+use super::{local_listener, spawn_service};
+use actix_web::{http, test, App};
+use routes::limits::DEFAULT_JSON_LIMIT;
+use server::ServerOptions;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn limits(json: usize, payload: Option<usize>) -> web::Data<BodyLimits> {
+    web::Data::new(BodyLimits { json, payload })
+}
+
+#[actix_web::test]
+async fn test_parse_duration() {
+    assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+    assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+    assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+    assert!(parse_duration("").is_err());
+    assert!(parse_duration("5d").is_err());
+}
+
+#[actix_web::test]
+async fn test_shutdown_timeout_whole_seconds() {
+    assert_eq!(parse_whole_seconds("2s"), Ok(Duration::from_secs(2)));
+    assert_eq!(parse_whole_seconds("1m"), Ok(Duration::from_secs(60)));
+    assert!(parse_whole_seconds("500ms").is_err());
+    assert!(parse_whole_seconds("1.5s").is_err());
+
+    // Set without the parser, the timeout is rounded up, as reported
+    let options = ServerOptions {
+        shutdown_timeout: Duration::from_millis(500),
+        ..Default::default()
+    };
+    assert_eq!(options.shutdown_timeout_secs(), 1);
+    assert_eq!(options.report()["shutdown_timeout_ms"], 1000);
+}
+
+#[actix_web::test]
+async fn test_payload_limit() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(limits(DEFAULT_JSON_LIMIT, Some(8)))
+            .wrap(from_fn(limit_payload))
+            .service(upload),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/upload?json")
+        .set_payload("hello")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let req = test::TestRequest::put()
+        .uri("/upload?json")
+        .set_payload("hello world")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Without `Content-Length`, the body fails once past the limit
+    let mut req = test::TestRequest::put()
+        .uri("/upload?json")
+        .set_payload("hello world")
+        .to_request();
+    req.headers_mut().remove(header::CONTENT_LENGTH);
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[actix_web::test]
+async fn test_json_limit() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .app_data(limits(16, None))
+            .service(echo),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/echo?json")
+        .set_json(json!({ "a": 1 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let req = test::TestRequest::post()
+        .uri("/echo?json")
+        .set_json(json!({ "message": "longer than the limit" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[actix_web::test]
+async fn test_config_reports_server_options() {
+    let options = ServerOptions {
+        workers: 3,
+        keep_alive: Duration::ZERO,
+        ..Default::default()
+    };
+    let config = web::Data::new(EffectiveConfig(json!({
+        "server": options.report(),
+        "body_limits": limits(1024, Some(2048)).report(),
+    })));
+    let app =
+        test::init_service(App::new().configure(|cfg| routes::config::configure(cfg, config)))
+            .await;
+
+    let req = test::TestRequest::get().uri("/_config").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(body["server"]["workers"], 3);
+    assert_eq!(body["server"]["keep_alive_ms"], 0);
+    assert_eq!(body["server"]["client_request_timeout_ms"], 5000);
+    assert_eq!(
+        body["body_limits"],
+        json!({ "json": 1024, "payload": 2048 })
+    );
+}
+
+#[actix_web::test]
+async fn test_client_request_timeout() {
+    let (addr, listener) = local_listener(ListenerOptions::default());
+    let handle = spawn_service(
+        || App::new().service(hello),
+        vec![listener],
+        &ServerOptions {
+            workers: 1,
+            client_request_timeout: Duration::from_millis(100),
+            ..Default::default()
+        },
+    );
+
+    // An incomplete head is answered 408 once the timeout expires
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    handle.stop(false).await;
+
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 408"));
+}
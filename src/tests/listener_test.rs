@@ -0,0 +1,137 @@
+use super::super::*;
+use super::spawn_service;
+use actix_web::App;
+use server::listener::{bind, parse_listen, ListenAddress, ListenSpec, Socket, BACKLOG};
+use server::{ListenerOptions, ServerOptions};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+
+/// Send a request on `io` and return the connection report of its response
+async fn report<T: AsyncRead + AsyncWrite + Unpin>(mut io: T) -> Value {
+    io.write_all(b"GET /?json HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    io.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+    let body: Value = serde_json::from_str(body).unwrap();
+    body["connection"].clone()
+}
+
+#[test]
+fn test_parse_listen() {
+    assert_eq!(
+        parse_listen("0.0.0.0:9999"),
+        Ok(ListenSpec::new(ListenAddress::Tcp(
+            "0.0.0.0:9999".to_string()
+        )))
+    );
+    assert_eq!(
+        parse_listen("[::]:9443,tls,no-h2c,proxy-protocol=required"),
+        Ok(ListenSpec {
+            tls: Some(true),
+            h2c: Some(false),
+            proxy_protocol: Some(ProxyProtocolMode::Required),
+            ..ListenSpec::new(ListenAddress::Tcp("[::]:9443".to_string()))
+        })
+    );
+    assert_eq!(
+        parse_listen("unix:/run/wester.sock,no-tls"),
+        Ok(ListenSpec {
+            tls: Some(false),
+            ..ListenSpec::new(ListenAddress::Unix("/run/wester.sock".into()))
+        })
+    );
+    assert_eq!(
+        parse_listen("fd:web,h2c"),
+        Ok(ListenSpec {
+            h2c: Some(true),
+            ..ListenSpec::new(ListenAddress::Fd("web".to_string()))
+        })
+    );
+    assert!(parse_listen("unix:").is_err());
+    assert!(parse_listen("fd:").is_err());
+    assert!(parse_listen("localhost").is_err());
+    assert!(parse_listen("localhost:9999,bogus").is_err());
+    assert!(parse_listen("localhost:9999,proxy-protocol=bogus").is_err());
+}
+
+#[actix_web::test]
+async fn test_listeners_report_where_requests_came_from() {
+    let path = std::env::temp_dir().join(format!("rustwester-{}.sock", std::process::id()));
+    let specs = [
+        parse_listen("127.0.0.1:0").unwrap(),
+        parse_listen(&format!("unix:{},proxy-protocol=optional", path.display())).unwrap(),
+    ];
+    let listeners = bind(&specs, &ListenerOptions::default(), BACKLOG, Vec::new()).unwrap();
+    let addr = listeners[0].local_addr().unwrap();
+    let handle = spawn_service(
+        || {
+            App::new()
+                .app_data(web::Data::new(AppState { allow_json: true }))
+                .wrap(from_fn(connection))
+                .service(hello)
+        },
+        listeners,
+        &ServerOptions::default(),
+    );
+
+    let tcp = report(TcpStream::connect(addr).await.unwrap()).await;
+    let unix = report(UnixStream::connect(&path).await.unwrap()).await;
+    handle.stop(false).await;
+    // The server may already have removed its socket
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(tcp["listener"]["name"], addr.to_string());
+    assert_eq!(tcp["listener"]["transport"], "tcp");
+    assert_eq!(tcp["listener"]["proxy_protocol"], "off");
+    assert_eq!(tcp["local_addr"], addr.to_string());
+    assert_eq!(unix["listener"]["name"], format!("unix:{}", path.display()));
+    assert_eq!(unix["listener"]["transport"], "unix");
+    assert_eq!(unix["listener"]["proxy_protocol"], "optional");
+    assert!(unix["local_addr"].is_null());
+}
+
+#[test]
+fn test_ipv6_wildcard_next_to_ipv4_listener() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let specs = [
+        parse_listen(&format!("127.0.0.1:{}", port)).unwrap(),
+        parse_listen(&format!("[::]:{}", port)).unwrap(),
+    ];
+    let listeners = bind(&specs, &ListenerOptions::default(), BACKLOG, Vec::new()).unwrap();
+
+    assert_eq!(listeners[0].name, format!("127.0.0.1:{}", port));
+    assert_eq!(listeners[1].name, format!("[::]:{}", port));
+}
+
+#[test]
+fn test_tls_listener_needs_certificate() {
+    let specs = [parse_listen("127.0.0.1:0,tls").unwrap()];
+    assert!(bind(&specs, &ListenerOptions::default(), BACKLOG, Vec::new()).is_err());
+}
+
+#[test]
+fn test_listeners_from_systemd_sockets() {
+    let web = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = web.local_addr().unwrap();
+    let unused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let inherited = vec![
+        ("web".to_string(), Socket::Tcp(web)),
+        ("other".to_string(), Socket::Tcp(unused)),
+    ];
+    let specs = [parse_listen("fd:web,h2c").unwrap()];
+    let listeners = bind(&specs, &ListenerOptions::default(), BACKLOG, inherited).unwrap();
+
+    assert_eq!(listeners.len(), 1);
+    assert_eq!(listeners[0].local_addr(), Some(addr));
+    assert!(listeners[0].options.h2c);
+
+    let specs = [parse_listen("fd:missing").unwrap()];
+    assert!(bind(&specs, &ListenerOptions::default(), BACKLOG, Vec::new()).is_err());
+}
@@ -1,2 +1,111 @@
+use crate::server::listener::Listener;
+use crate::server::{self, ListenerOptions, ServerOptions};
+use actix_http::{Request, Response};
+use actix_service::IntoServiceFactory;
+use actix_web::body::MessageBody;
+use actix_web::dev::{AppConfig, ServerHandle, Service, ServiceFactory};
+use actix_web::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[cfg(test)]
+pub mod admin_test;
+#[cfg(test)]
+pub mod cache_test;
+#[cfg(test)]
+pub mod client_ip_test;
+#[cfg(test)]
+pub mod compression_test;
+#[cfg(test)]
+pub mod config_test;
+#[cfg(test)]
+pub mod connection_test;
+#[cfg(test)]
+pub mod cookies_test;
+#[cfg(test)]
+pub mod cors_test;
+#[cfg(test)]
+pub mod download_test;
+#[cfg(test)]
+pub mod echo_test;
+#[cfg(test)]
+pub mod files_test;
+#[cfg(test)]
+pub mod grpc_test;
+#[cfg(test)]
+pub mod h2c_test;
+#[cfg(test)]
+pub mod h3_test;
 #[cfg(test)]
 pub mod integration_test;
+#[cfg(test)]
+pub mod jwt_test;
+#[cfg(test)]
+pub mod lifecycle_test;
+#[cfg(test)]
+pub mod limits_test;
+#[cfg(test)]
+pub mod listener_test;
+#[cfg(test)]
+pub mod oidc_test;
+#[cfg(test)]
+pub mod proxy_protocol_test;
+#[cfg(test)]
+pub mod range_test;
+#[cfg(test)]
+pub mod redirect_test;
+#[cfg(test)]
+pub mod reload_test;
+#[cfg(test)]
+pub mod upload_test;
+
+/// A TCP listener with `options` on a free local port, and its address
+pub fn local_listener(options: ListenerOptions) -> (SocketAddr, Listener) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    (addr, Listener::tcp(listener, options).unwrap())
+}
+
+/// Serve the app built by `factory` on `listeners` in the background, the way
+/// the server does
+pub fn spawn_service<F, I, S, B>(
+    factory: F,
+    listeners: Vec<Listener>,
+    options: &ServerOptions,
+) -> ServerHandle
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<Error> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    let server = server::serve(factory, listeners, options).unwrap();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+    handle
+}
+
+/// The answer to `GET path` with `headers`, asked alone on a new connection,
+/// so the client port and connection differ every time
+pub async fn get_alone(addr: SocketAddr, path: &str, headers: &[(&str, &str)]) -> String {
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n",
+        path
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
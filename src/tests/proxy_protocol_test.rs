@@ -0,0 +1,161 @@
+use super::super::*;
+use super::{local_listener, spawn_service};
+use actix_web::App;
+use routes::proxy_protocol::{parse_v1, parse_v2, Tlv};
+use server::{ListenerOptions, ServerOptions};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// A v2 PROXY TCP4 header from 192.0.2.1:56324 to 198.51.100.1:443
+fn v2_header(tlvs: &[u8]) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.extend_from_slice(&[0x21, 0x11]);
+    header.extend_from_slice(&(12 + tlvs.len() as u16).to_be_bytes());
+    header.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 1]);
+    header.extend_from_slice(&56324u16.to_be_bytes());
+    header.extend_from_slice(&443u16.to_be_bytes());
+    header.extend_from_slice(tlvs);
+    header
+}
+
+/// Send `header` then an echo request to a server started in `mode`, and
+/// return the status line and body
+async fn send(mode: ProxyProtocolMode, header: &[u8]) -> (String, Value) {
+    let (addr, listener) = local_listener(ListenerOptions {
+        proxy_protocol: mode,
+        ..Default::default()
+    });
+    let handle = spawn_service(
+        || {
+            App::new()
+                .app_data(web::Data::new(AppState { allow_json: true }))
+                .wrap(from_fn(proxy_protocol))
+                .wrap(from_fn(client_ip))
+                .service(echo)
+        },
+        vec![listener],
+        &ServerOptions::default(),
+    );
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(header).await.unwrap();
+    stream
+        .write_all(
+            b"POST /echo?json HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+              Content-Length: 2\r\nConnection: close\r\n\r\n{}",
+        )
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response).await;
+    handle.stop(false).await;
+
+    let response = String::from_utf8(response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.lines().next().unwrap_or_default().to_string();
+    (status, serde_json::from_str(body).unwrap_or(Value::Null))
+}
+
+#[test]
+fn test_parse_v1() {
+    let header = parse_v1(b"PROXY TCP6 2001:db8::1 2001:db8::2 4711 443\r\n").unwrap();
+
+    assert_eq!(header.version, 1);
+    assert_eq!(header.protocol, "TCP6");
+    assert_eq!(
+        header.source,
+        Some("[2001:db8::1]:4711".parse::<SocketAddr>().unwrap())
+    );
+    assert_eq!(header.destination.unwrap().port(), 443);
+
+    let header = parse_v1(b"PROXY UNKNOWN whatever\r\n").unwrap();
+    assert_eq!(header.protocol, "UNKNOWN");
+    assert!(header.source.is_none());
+
+    assert!(parse_v1(b"PROXY TCP4 2001:db8::1 192.0.2.1 1 2\r\n").is_err());
+    assert!(parse_v1(b"PROXY TCP4 192.0.2.1 192.0.2.2 1\r\n").is_err());
+}
+
+#[test]
+fn test_parse_v2_tlvs() {
+    let header = parse_v2(&v2_header(b"\x01\x00\x02h2\xea\x00\x04\x01vpc")).unwrap();
+
+    assert_eq!(header.version, 2);
+    assert_eq!(header.command, "PROXY");
+    assert_eq!(header.protocol, "TCP4");
+    assert_eq!(
+        header.source,
+        Some("192.0.2.1:56324".parse::<SocketAddr>().unwrap())
+    );
+    assert_eq!(
+        header.tlvs,
+        vec![
+            Tlv {
+                kind: 0x01,
+                value: b"h2".to_vec()
+            },
+            Tlv {
+                kind: 0xea,
+                value: b"\x01vpc".to_vec()
+            },
+        ]
+    );
+
+    assert!(parse_v2(&v2_header(b"\x01\x00\x05h2")).is_err());
+
+    let mut local = v2_header(b"");
+    local[12] = 0x20;
+    let header = parse_v2(&local).unwrap();
+    assert_eq!(header.command, "LOCAL");
+    assert!(header.source.is_none());
+}
+
+#[actix_web::test]
+async fn test_proxy_protocol_v1_in_echo() {
+    let (status, body) = send(
+        ProxyProtocolMode::Required,
+        b"PROXY TCP4 203.0.113.7 192.0.2.10 40000 9999\r\n",
+    )
+    .await;
+
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body["proxy_protocol"]["version"], 1);
+    assert_eq!(body["proxy_protocol"]["source"], "203.0.113.7:40000");
+    assert_eq!(body["proxy_protocol"]["destination"], "192.0.2.10:9999");
+    assert!(body["proxy_protocol"]["proxy"]
+        .as_str()
+        .unwrap()
+        .starts_with("127.0.0.1:"));
+    assert_eq!(body["client"]["client_ip"], "203.0.113.7");
+}
+
+#[actix_web::test]
+async fn test_proxy_protocol_v2_in_echo() {
+    let (status, body) = send(
+        ProxyProtocolMode::Optional,
+        &v2_header(b"\x02\x00\x0bexample.com\xea\x00\x04\x01vpc"),
+    )
+    .await;
+
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body["proxy_protocol"]["version"], 2);
+    assert_eq!(body["proxy_protocol"]["source"], "192.0.2.1:56324");
+    assert_eq!(body["proxy_protocol"]["tlvs"][0]["name"], "AUTHORITY");
+    assert_eq!(body["proxy_protocol"]["tlvs"][0]["value"], "example.com");
+    assert_eq!(body["proxy_protocol"]["tlvs"][1]["vpce_id"], "vpc");
+}
+
+#[actix_web::test]
+async fn test_proxy_protocol_modes_without_header() {
+    let (status, body) = send(ProxyProtocolMode::Optional, b"").await;
+
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.get("proxy_protocol").is_none());
+    assert_eq!(body["client"]["client_ip"], "127.0.0.1");
+
+    let (status, _) = send(ProxyProtocolMode::Required, b"").await;
+    assert_eq!(status, "");
+}
@@ -0,0 +1,115 @@
+use super::super::*;
+use actix_web::{http, test, App};
+
+#[actix_web::test]
+async fn test_range_full() {
+    let app = test::init_service(App::new().service(range)).await;
+
+    let req = test::TestRequest::get().uri("/range/30").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+
+    let body = test::read_body(resp).await;
+    assert_eq!(body, "abcdefghijklmnopqrstuvwxyzabcd");
+}
+
+#[actix_web::test]
+async fn test_range_single() {
+    let app = test::init_service(App::new().service(range)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/range/30")
+        .insert_header((header::RANGE, "bytes=2-5"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes 2-5/30"
+    );
+    assert_eq!(test::read_body(resp).await, "cdef");
+
+    let req = test::TestRequest::get()
+        .uri("/range/30")
+        .insert_header((header::RANGE, "bytes=-3"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes 27-29/30"
+    );
+    assert_eq!(test::read_body(resp).await, "bcd");
+}
+
+#[actix_web::test]
+async fn test_range_multipart() {
+    let app = test::init_service(App::new().service(range)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/range/30")
+        .insert_header((header::RANGE, "bytes=0-1,26-"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "multipart/byteranges; boundary=rustwester-byteranges"
+    );
+
+    let body = test::read_body(resp).await;
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains("Content-Range: bytes 0-1/30\r\n\r\nab\r\n"));
+    assert!(body.contains("Content-Range: bytes 26-29/30\r\n\r\nabcd\r\n"));
+    assert!(body.ends_with("--rustwester-byteranges--\r\n"));
+}
+
+#[actix_web::test]
+async fn test_range_not_satisfiable() {
+    let app = test::init_service(App::new().service(range)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/range/30")
+        .insert_header((header::RANGE, "bytes=40-50"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes */30"
+    );
+}
+
+#[actix_web::test]
+async fn test_range_if_range() {
+    let app = test::init_service(App::new().service(range)).await;
+
+    let req = test::TestRequest::get().uri("/range/30").to_request();
+    let resp = test::call_service(&app, req).await;
+    let etag = resp.headers().get(header::ETAG).unwrap().clone();
+
+    let req = test::TestRequest::get()
+        .uri("/range/30")
+        .insert_header((header::RANGE, "bytes=0-1"))
+        .insert_header((header::IF_RANGE, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::PARTIAL_CONTENT);
+
+    // A stale validator gets the whole content
+    let req = test::TestRequest::get()
+        .uri("/range/30")
+        .insert_header((header::RANGE, "bytes=0-1"))
+        .insert_header((header::IF_RANGE, "\"range-31\""))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(test::read_body(resp).await.len(), 30);
+}
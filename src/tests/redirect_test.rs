@@ -0,0 +1,117 @@
+use super::super::*;
+use actix_web::{http, test, App};
+
+fn location<B>(resp: &actix_web::dev::ServiceResponse<B>) -> &str {
+    resp.headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_redirect_relative() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .configure(routes::redirect::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/redirect/2?json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::FOUND);
+    assert_eq!(location(&resp), "/redirect/1?json");
+
+    let req = test::TestRequest::get()
+        .uri("/redirect/0?json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["scheme"], "http");
+}
+
+#[actix_web::test]
+async fn test_redirect_absolute_behind_proxy() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .configure(routes::redirect::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/redirect/3?absolute&status_code=307")
+        .insert_header(("X-Forwarded-Proto", "https"))
+        .insert_header(("X-Forwarded-Host", "public.example.com"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        location(&resp),
+        "https://public.example.com/redirect/2?absolute&status_code=307"
+    );
+}
+
+#[actix_web::test]
+async fn test_redirect_to() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .configure(routes::redirect::configure),
+    )
+    .await;
+
+    for code in [301, 302, 303, 307, 308] {
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/redirect-to?url=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc&status_code={}",
+                code
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status().as_u16(), code);
+        assert_eq!(location(&resp), "https://example.com/a?b=c");
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/redirect-to?url=/&status_code=200")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_redirect_loop() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .configure(routes::redirect::configure),
+    )
+    .await;
+
+    let mut uri = "/redirect-loop?max=3&json".to_string();
+    for hop in 1..=3 {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), http::StatusCode::FOUND);
+        uri = location(&resp).to_string();
+        assert!(uri.contains(&format!("hop={}", hop)));
+    }
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::LOOP_DETECTED);
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(result["response"]["hops"], 3);
+}
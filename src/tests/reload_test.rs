@@ -0,0 +1,119 @@
+use super::super::*;
+use actix_web::{http, test, App};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use utils::config::{changes, parse_from};
+
+/// The settings following `args`, as they are on startup and on reload
+fn settings_from(args: &[&str]) -> Settings {
+    let (cli, effective) =
+        parse_from::<Cli, _, _>(std::iter::once("rustwester").chain(args.iter().copied())).unwrap();
+    settings(&cli, &json!({}), &effective).unwrap()
+}
+
+#[actix_web::test]
+async fn test_settings_replaced_between_requests() {
+    let reloadable = web::Data::new(Reloadable::new(settings_from(&[
+        "--json-limit",
+        "16",
+        "--cors-origin",
+        "https://app.example.com",
+    ])));
+    let app = test::init_service(
+        App::new()
+            .app_data(reloadable.clone())
+            .wrap(from_fn(cors))
+            .wrap(from_fn(apply_settings))
+            .service(echo),
+    )
+    .await;
+    let request = || {
+        test::TestRequest::post()
+            .uri("/echo?json")
+            .insert_header((header::ORIGIN, "https://app.example.com"))
+            .set_json(json!({ "message": "longer than the limit" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request()).await;
+    assert_eq!(resp.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(resp
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    // Settings left out of the new configuration are disabled
+    reloadable.replace(settings_from(&[]));
+    let resp = test::call_service(&app, request()).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert!(!resp
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[actix_web::test]
+async fn test_config_changes() {
+    let old = json!({ "port": 9999, "no-json": false, "cors-origin": ["a"] });
+    let new = json!({ "port": 9999, "no-json": true, "json-limit": "1MiB" });
+
+    assert_eq!(
+        changes(old.as_object().unwrap(), new.as_object().unwrap()),
+        vec![
+            ("cors-origin".to_string(), json!(["a"]), Value::Null),
+            ("json-limit".to_string(), Value::Null, json!("1MiB")),
+            ("no-json".to_string(), json!(false), json!(true)),
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn test_reload_on_file_change() {
+    let path = std::env::temp_dir().join(format!("rustwester-{}-watch.toml", std::process::id()));
+    fs::write(&path, "port = 8080\n").unwrap();
+    let reloads = Arc::new(AtomicUsize::new(0));
+    let counter = reloads.clone();
+    let watch = server::reload::on_reload(Some(path.clone()), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    })
+    .unwrap();
+    let watch = actix_web::rt::spawn(watch);
+
+    tokio::time::sleep(server::reload::WATCH_INTERVAL * 2).await;
+    assert_eq!(reloads.load(Ordering::SeqCst), 0);
+
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    tokio::time::sleep(server::reload::WATCH_INTERVAL * 2).await;
+    watch.abort();
+
+    assert_eq!(reloads.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn test_reload_keeps_settings_on_invalid_file() {
+    let path = std::env::temp_dir().join(format!(
+        "rustwester-{}-reload-requires.toml",
+        std::process::id()
+    ));
+    fs::write(&path, "admin-password = \"hunter2\"\njson-limit = \"16\"\n").unwrap();
+    let args: Vec<std::ffi::OsString> = ["rustwester", "--admin-user", "admin", "--config"]
+        .into_iter()
+        .map(Into::into)
+        .chain(std::iter::once(path.clone().into_os_string()))
+        .collect();
+    let (cli, effective) = parse_from::<Cli, _, _>(&args).unwrap();
+    let reloadable = Reloadable::new(settings(&cli, &json!({}), &effective).unwrap());
+    let current = std::sync::Mutex::new(effective);
+
+    // --admin-user now misses its --admin-password
+    fs::write(&path, "json-limit = \"32\"\n").unwrap();
+    reload(&args, &current, &reloadable, &json!({}));
+
+    assert_eq!(reloadable.current().body_limits.json, 16);
+    assert_eq!(current.lock().unwrap().options["json-limit"], 16);
+}
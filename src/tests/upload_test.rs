@@ -0,0 +1,97 @@
+use super::super::*;
+use actix_web::{http, test, App};
+
+#[actix_web::test]
+async fn test_upload_raw_body() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(upload),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/upload?json")
+        .insert_header(header::ContentType::octet_stream())
+        .set_payload("hello world")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    let report = &result["response"];
+    assert_eq!(report["size"], 11);
+    assert_eq!(report["chunks"], 1);
+    assert_eq!(
+        report["sha256"],
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+    );
+    assert_eq!(report["md5"], "5eb63bbbe01eeed093cb22bb8f5acdc3");
+    assert_eq!(report["crc32"], "0d4a1185");
+    assert_eq!(report["content_type"], "application/octet-stream");
+    assert!(report["duration_ms"].is_f64());
+    assert!(report.get("files").is_none());
+}
+
+#[actix_web::test]
+async fn test_upload_multipart() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(upload),
+    )
+    .await;
+
+    let body = concat!(
+        "--boundary\r\n",
+        "Content-Disposition: form-data; name=\"first\"; filename=\"a.txt\"\r\n",
+        "Content-Type: text/plain\r\n\r\n",
+        "hello world\r\n",
+        "--boundary\r\n",
+        "Content-Disposition: form-data; name=\"second\"; filename=\"b.bin\"\r\n",
+        "Content-Type: application/octet-stream\r\n\r\n",
+        "\r\n",
+        "--boundary--\r\n",
+    );
+    let req = test::TestRequest::post()
+        .uri("/upload?json")
+        .insert_header((
+            header::CONTENT_TYPE,
+            "multipart/form-data; boundary=boundary",
+        ))
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    let result: serde_json::Value = test::read_body_json(resp).await;
+    let report = &result["response"];
+    assert_eq!(report["size"], body.len());
+
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["field"], "first");
+    assert_eq!(files[0]["filename"], "a.txt");
+    assert_eq!(files[0]["content_type"], "text/plain");
+    assert_eq!(files[0]["size"], 11);
+    assert_eq!(files[0]["md5"], "5eb63bbbe01eeed093cb22bb8f5acdc3");
+    assert_eq!(files[1]["filename"], "b.bin");
+    assert_eq!(files[1]["size"], 0);
+}
+
+#[actix_web::test]
+async fn test_upload_get_not_allowed() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState { allow_json: true }))
+            .service(upload),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/upload").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,293 @@
+use super::structs::{Result, WesterError};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, ValueEnum};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Id of the option naming the configuration file
+const CONFIG: &str = "config";
+
+/// Shown instead of the value of secret options
+const REDACTED: &str = "<redacted>";
+
+/// Whether an option holds a secret, rather than a path to one
+fn is_secret(arg: &Arg) -> bool {
+    let id = arg.get_id().as_str();
+    ["secret", "password", "token"]
+        .iter()
+        .any(|word| id.contains(word))
+}
+
+/// Options read from a TOML or YAML configuration file, by the long name of
+/// the flag setting them, with `-` or `_`
+pub fn load(path: &Path) -> Result<Map<String, Value>> {
+    let content = std::fs::read_to_string(path)?;
+    let invalid = |err: String| {
+        WesterError::Config(format!(
+            "Invalid configuration file {}: {}",
+            path.display(),
+            err
+        ))
+    };
+    let value: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|err| invalid(err.to_string()))?,
+        Some("yaml" | "yml") => {
+            serde_norway::from_str(&content).map_err(|err| invalid(err.to_string()))?
+        }
+        _ => {
+            return Err(invalid(
+                "expected a .toml, .yaml or .yml extension".to_string(),
+            ))
+        }
+    };
+    match value {
+        Value::Object(options) => Ok(options),
+        // An empty YAML file
+        Value::Null => Ok(Map::new()),
+        _ => Err(invalid("expected a table of options".to_string())),
+    }
+}
+
+fn find_arg<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    let long = key.replace('_', "-");
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(long.as_str()))
+}
+
+/// The values of an option in a configuration file, as they would be given
+/// on the command line
+fn file_values(key: &str, value: &Value) -> std::result::Result<Vec<String>, String> {
+    let scalar = |value: &Value| match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        _ => Err(format!(
+            "option {} must be a string, a number, a boolean or a list of them",
+            key
+        )),
+    };
+    match value {
+        Value::Array(values) => values.iter().map(scalar).collect(),
+        value => Ok(vec![scalar(value)?]),
+    }
+}
+
+/// The options of `file` as arguments, leaving out those already set by
+/// flags or environment variables, which take precedence over the file, with
+/// the long names of the options kept
+fn file_args(
+    command: &Command,
+    matches: &ArgMatches,
+    file: &Map<String, Value>,
+) -> std::result::Result<(Vec<String>, HashSet<String>), String> {
+    let mut args = Vec::new();
+    let mut kept = HashSet::new();
+    for (key, value) in file {
+        let Some(arg) = find_arg(command, key).filter(|arg| arg.get_id() != CONFIG) else {
+            return Err(format!("unknown option {}", key));
+        };
+        let long = arg.get_long().unwrap_or_default();
+        if matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        let values = file_values(key, value)?;
+        kept.insert(long.to_string());
+        match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::SetFalse => match values.as_slice() {
+                [value] if value == "true" => args.push(format!("--{}", long)),
+                [value] if value == "false" => {}
+                _ => return Err(format!("option {} must be a boolean", key)),
+            },
+            ArgAction::Count => {
+                let count: usize = match values.as_slice() {
+                    [value] => value.parse().ok(),
+                    _ => None,
+                }
+                .ok_or_else(|| format!("option {} must be a number", key))?;
+                args.extend((0..count).map(|_| format!("--{}", long)));
+            }
+            _ => args.extend(values.iter().map(|value| format!("--{}={}", long, value))),
+        }
+    }
+    Ok((args, kept))
+}
+
+/// A value as written in a configuration file, numbers and booleans unquoted
+fn typed_value(value: String) -> Value {
+    match serde_json::from_str(&value) {
+        Ok(value @ (Value::Number(_) | Value::Bool(_))) => value,
+        _ => Value::String(value),
+    }
+}
+
+/// The value of an option as the application sees it, secrets redacted
+fn effective_value(arg: &Arg, matches: &ArgMatches) -> Option<Value> {
+    let mut values: Vec<Value> = matches
+        .try_get_raw(arg.get_id().as_str())
+        .ok()
+        .flatten()?
+        .map(|value| typed_value(value.to_string_lossy().to_string()))
+        .collect();
+    if is_secret(arg) {
+        return Some(json!(REDACTED));
+    }
+    match matches!(arg.get_action(), ArgAction::Append) || arg.get_value_delimiter().is_some() {
+        true => Some(Value::Array(values)),
+        false => values.pop(),
+    }
+}
+
+/// The options whose value differs from `old` to `new`, with both values
+pub fn changes(old: &Map<String, Value>, new: &Map<String, Value>) -> Vec<(String, Value, Value)> {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| {
+            let value =
+                |options: &Map<String, Value>| options.get(key).cloned().unwrap_or_default();
+            (key.clone(), value(old), value(new))
+        })
+        .collect()
+}
+
+/// Formats `config print` writes the configuration in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// The configuration in effect, as options, by long name, and where each of
+/// them comes from
+pub struct EffectiveOptions {
+    pub options: Map<String, Value>,
+    pub sources: Map<String, Value>,
+}
+
+impl EffectiveOptions {
+    fn new(command: &Command, matches: &ArgMatches, file_keys: &HashSet<String>) -> Self {
+        let mut options = Map::new();
+        let mut sources = Map::new();
+        for arg in command.get_arguments() {
+            let (Some(long), Some(value)) = (arg.get_long(), effective_value(arg, matches)) else {
+                continue;
+            };
+            if arg.get_id() == CONFIG
+                || matches!(
+                    arg.get_action(),
+                    ArgAction::Help
+                        | ArgAction::HelpShort
+                        | ArgAction::HelpLong
+                        | ArgAction::Version
+                )
+            {
+                continue;
+            }
+            let source = match matches.value_source(arg.get_id().as_str()) {
+                _ if file_keys.contains(long) => "file",
+                Some(ValueSource::CommandLine) => "command line",
+                Some(ValueSource::EnvVariable) => "environment",
+                _ => "default",
+            };
+            options.insert(long.to_string(), value);
+            sources.insert(long.to_string(), json!(source));
+        }
+        Self { options, sources }
+    }
+
+    /// The options in `format`, usable as a configuration file once the
+    /// secrets are filled in
+    pub fn render(&self, format: ConfigFormat) -> Result<String> {
+        let invalid = |err: String| WesterError::Config(format!("Can't render options: {}", err));
+        match format {
+            ConfigFormat::Toml => {
+                toml::to_string(&self.options).map_err(|err| invalid(err.to_string()))
+            }
+            ConfigFormat::Yaml => {
+                serde_norway::to_string(&self.options).map_err(|err| invalid(err.to_string()))
+            }
+            ConfigFormat::Json => Ok(serde_json::to_string_pretty(&self.options)? + "\n"),
+        }
+    }
+}
+
+/// Parse the command line, the environment and the configuration file named
+/// by `--config`, in this order of precedence, before the defaults
+pub fn parse<C: CommandFactory + FromArgMatches>() -> Result<(C, EffectiveOptions)> {
+    parse_from(std::env::args_os())
+}
+
+/// Parse `args` as `parse` does the command line
+pub fn parse_from<C, I, T>(args: I) -> Result<(C, EffectiveOptions)>
+where
+    C: CommandFactory + FromArgMatches,
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    // The file is only known once the command line is parsed, while the
+    // requirements its options may satisfy are only checked with them
+    let matches = C::command()
+        .ignore_errors(true)
+        .get_matches_from(args.clone());
+    let path = matches.get_one::<PathBuf>(CONFIG).cloned();
+    let (file_args, file_keys) = match &path {
+        Some(path) => {
+            let invalid = |err: String| {
+                WesterError::Config(format!(
+                    "Invalid configuration file {}: {}",
+                    path.display(),
+                    err
+                ))
+            };
+            file_args(&C::command(), &matches, &load(path)?).map_err(invalid)?
+        }
+        None => Default::default(),
+    };
+
+    let command = C::command();
+    // Before any subcommand, for clap to check requirements at the top level
+    let all_args = args
+        .iter()
+        .take(1)
+        .cloned()
+        .chain(file_args.into_iter().map(OsString::from))
+        .chain(args.iter().skip(1).cloned());
+    let matches = match command.clone().try_get_matches_from(all_args) {
+        Ok(matches) => matches,
+        Err(err) => match (&path, C::command().try_get_matches_from(args)) {
+            // The options of the file are at fault
+            (Some(path), Ok(_)) => {
+                let message = err.render().to_string();
+                let message: Vec<&str> = message
+                    .lines()
+                    .take_while(|line| !line.is_empty())
+                    .map(str::trim)
+                    .collect();
+                return Err(WesterError::Config(format!(
+                    "Invalid configuration file {}: {}",
+                    path.display(),
+                    message.join(" ").trim_start_matches("error: ")
+                )));
+            }
+            // Only help and version end the process, on startup
+            _ if !err.use_stderr() => err.exit(),
+            _ => {
+                return Err(WesterError::Config(
+                    err.render().to_string().trim_end().to_string(),
+                ))
+            }
+        },
+    };
+    let cli = C::from_arg_matches(&matches).map_err(|err| WesterError::Config(err.to_string()))?;
+    Ok((cli, EffectiveOptions::new(&command, &matches, &file_keys)))
+}
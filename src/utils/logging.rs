@@ -128,16 +128,18 @@ pub fn log_init(
     use_json: bool,
     log_file_location: Option<PathBuf>,
 ) -> Result<()> {
+    // Dispatchers accept everything, the global max level does the filtering
+    // so it can be changed at runtime
     let stdout_dispatcher = Dispatch::new()
         .format(make_formatter(true, use_json))
-        .level(log_level)
+        .level(LevelFilter::Trace)
         .chain(std::io::stdout());
 
     match log_file_location {
         Some(lfl) => {
             let file_dispatcher = Dispatch::new()
                 .format(make_formatter(false, use_json))
-                .level(log_level)
+                .level(LevelFilter::Trace)
                 .chain(log_file(lfl)?);
 
             fern::Dispatch::new()
@@ -149,6 +151,7 @@ pub fn log_init(
             fern::Dispatch::new().chain(stdout_dispatcher).apply()?;
         }
     }
+    log::set_max_level(log_level);
 
     Ok(())
 }
@@ -12,8 +12,16 @@ pub enum WesterError {
     ActixUrl(#[from] actix_web::Error),
     #[error("Set Logger Error: {0}")]
     SetLoggerError(#[from] SetLoggerError),
-    // #[error("Error: {0}")]
-    // Other(String),
+    #[error("JWT Error: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error("Regex Error: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("TLS Error: {0}")]
+    Tls(#[from] rustls::Error),
+    #[error("Configuration Error: {0}")]
+    Config(String),
+    #[error("Error: {0}")]
+    Other(String),
 }
 
 /// A `Result` alias where the `Err` case is `ClodociError`.
@@ -34,7 +42,20 @@ impl ResponseError for WesterError {
             }
             WesterError::SetLoggerError(ref err) => {
                 HttpResponse::InternalServerError().body(format!("Set Logger Error: {}", err))
-            } // WesterError::Other(ref err) => HttpResponse::InternalServerError().body(err.clone()),
+            }
+            WesterError::Jwt(ref err) => {
+                HttpResponse::InternalServerError().body(format!("JWT Error: {}", err))
+            }
+            WesterError::Regex(ref err) => {
+                HttpResponse::InternalServerError().body(format!("Regex Error: {}", err))
+            }
+            WesterError::Tls(ref err) => {
+                HttpResponse::InternalServerError().body(format!("TLS Error: {}", err))
+            }
+            WesterError::Config(ref err) => {
+                HttpResponse::InternalServerError().body(format!("Configuration Error: {}", err))
+            }
+            WesterError::Other(ref err) => HttpResponse::InternalServerError().body(err.clone()),
         }
     }
 }